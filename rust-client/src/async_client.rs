@@ -0,0 +1,124 @@
+use std::io::{Result as IoResult, Error as IoError, ErrorKind};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+use crate::{Bot, Error, Event, GameInfo, Move};
+use crate::event::{Decoded, Decoder};
+use crate::handshake;
+
+/// The same as `Connection`, but on a tokio `TcpStream`.
+pub struct AsyncConnection {
+    stream: TcpStream,
+    token: Option<String>,
+    decoder: Decoder,
+}
+
+impl AsyncConnection {
+    pub async fn next_event(&mut self) -> Result<Event, Error> {
+        let c = self.next_char().await?;
+        match self.decoder.decode(c)? {
+            Decoded::Event(event) => Ok(event),
+            Decoded::NewGameWithInfo => {
+                let line = read_line(&mut self.stream).await?;
+                Ok(Event::NewGame(Some(GameInfo::parse(&line)?)))
+            },
+            Decoded::AbortedWithSync => {
+                let token = read_line(&mut self.stream).await?;
+                let mut reply = vec![token.len() as u8];
+                reply.extend_from_slice(token.as_bytes());
+                self.stream.write_all(&reply).await?;
+                self.stream.flush().await?;
+                Ok(Event::Aborted)
+            },
+        }
+    }
+    pub async fn send_move(&mut self, m: Move) -> IoResult<()> {
+        self.send_byte(m.to_byte()).await
+    }
+    pub async fn next_char(&mut self) -> IoResult<u8> {
+        loop {
+            let c = self.stream.read_u8().await?;
+            if c == b' ' {
+                self.stream.write_all(b" ").await?;
+                self.stream.flush().await?;
+            } else {
+                return Ok(c);
+            }
+        }
+    }
+    pub async fn send_byte(&mut self, c: u8) -> IoResult<()> {
+        self.stream.write_all(&[c]).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+    /// The session token given by the server, if connected with
+    /// `connect_session`.
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+    pub async fn connect<A: ToSocketAddrs>(addr: A, name: &str) -> Result<AsyncConnection, Error> {
+        let handshake = handshake::legacy(name)?;
+        let mut conn = TcpStream::connect(addr).await?;
+        conn.write_all(&handshake).await?;
+        conn.flush().await?;
+        Ok(AsyncConnection {
+            stream: conn,
+            token: None,
+            decoder: Decoder::new(&[]),
+        })
+    }
+    pub async fn connect_session<A: ToSocketAddrs>(
+        addr: A,
+        name: &str,
+        token: Option<&str>,
+    ) -> Result<AsyncConnection, Error> {
+        AsyncConnection::connect_session_with(addr, name, token, &[]).await
+    }
+    /// Connects using the extended handshake with the given capabilities,
+    /// like `Connection::connect_session_with`.
+    pub async fn connect_session_with<A: ToSocketAddrs>(
+        addr: A,
+        name: &str,
+        token: Option<&str>,
+        capabilities: &[&str],
+    ) -> Result<AsyncConnection, Error> {
+        let handshake = handshake::extended(name, token, capabilities)?;
+        let mut conn = TcpStream::connect(addr).await?;
+        conn.write_all(&handshake).await?;
+        conn.flush().await?;
+        let token = handshake::parse_reply(&read_line(&mut conn).await?)?;
+        Ok(AsyncConnection {
+            stream: conn,
+            token,
+            decoder: Decoder::new(capabilities),
+        })
+    }
+}
+
+async fn read_line(stream: &mut TcpStream) -> IoResult<String> {
+    let len = stream.read_u8().await?;
+    let mut line = vec![0; usize::from(len)];
+    stream.read_exact(&mut line).await?;
+    String::from_utf8(line)
+        .map_err(|_| IoError::new(ErrorKind::InvalidData, "line invalid utf8"))
+}
+
+/// Plays games with the bot until the server shuts the connection down, like
+/// `run`.
+pub async fn run_async<B: Bot>(connection: &mut AsyncConnection, bot: &mut B) -> Result<(), Error> {
+    loop {
+        match connection.next_event().await? {
+            Event::NewGame(info) => {
+                bot.new_game(info.as_ref());
+                connection.send_move(bot.choose()).await?;
+            },
+            Event::OpponentMoved(m) => {
+                bot.observe(m);
+                connection.send_move(bot.choose()).await?;
+            },
+            Event::GameEnded(m) => bot.observe(m),
+            Event::Aborted => {},
+            Event::Shutdown => return Ok(()),
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use std::env::args;
+
+use rust_client::{AsyncConnection, Bot, Move, run_async};
+
+/// Plays rock, paper and scissor in turn, starting at a different move for
+/// each bot in the swarm.
+struct Cycle {
+    next: usize,
+}
+impl Bot for Cycle {
+    fn choose(&mut self) -> Move {
+        self.next = (self.next + 1) % 3;
+        [Move::Rock, Move::Paper, Move::Scissor][self.next]
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let mut args = args().skip(1);
+    let (addr, count) = match (args.next(), args.next()) {
+        (Some(addr), Some(count)) => (addr, count),
+        _ => {
+            println!("Usage: swarm <addr> <count> [prefix]");
+            return;
+        },
+    };
+    let count: usize = match count.parse() {
+        Ok(count) => count,
+        Err(_) => {
+            println!("{} is not a number.", count);
+            return;
+        },
+    };
+    let prefix = args.next().unwrap_or_else(|| String::from("swarm"));
+    let mut tasks = Vec::new();
+    for i in 0..count {
+        let addr = addr.clone();
+        let name = format!("{}-{}", prefix, i);
+        tasks.push(tokio::spawn(async move {
+            let mut connection = AsyncConnection::connect(addr, &name).await?;
+            run_async(&mut connection, &mut Cycle { next: i % 3 }).await
+        }));
+    }
+    let mut failed = 0;
+    for task in tasks {
+        match task.await {
+            Ok(Ok(())) => {},
+            Ok(Err(err)) => {
+                println!("{}", err);
+                failed += 1;
+            },
+            Err(err) => {
+                println!("{}", err);
+                failed += 1;
+            },
+        }
+    }
+    println!("{} bots finished, {} failed.", count - failed, failed);
+}
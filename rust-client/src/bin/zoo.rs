@@ -0,0 +1,39 @@
+use std::env::args;
+
+use rust_client::{Connection, run, zoo};
+
+fn main() {
+    let mut args = args().skip(1);
+    let (addr, bot) = match (args.next(), args.next()) {
+        (Some(addr), Some(bot)) => (addr, bot),
+        _ => {
+            println!("Usage: zoo <addr> <bot> [name] [seed]");
+            println!("The bots are {}.", zoo::NAMES.join(", "));
+            return;
+        },
+    };
+    let name = args.next().unwrap_or_else(|| bot.clone());
+    let seed = match args.next().map(|seed| seed.parse::<u64>()) {
+        Some(Ok(seed)) => seed,
+        Some(Err(_)) => {
+            println!("The seed must be a number.");
+            return;
+        },
+        None => 0,
+    };
+    let mut bot = match zoo::by_name(&bot, seed) {
+        Some(bot) => bot,
+        None => {
+            println!("There is no bot called {}. The bots are {}.", bot, zoo::NAMES.join(", "));
+            return;
+        },
+    };
+    let connection = Connection::connect_session_with(addr, &name, None, &["reference", "seed", "abort"]);
+    let result = connection.and_then(|mut connection| {
+        run(&mut connection, &mut bot)
+    });
+    match result {
+        Ok(()) => println!("goodbye"),
+        Err(err) => println!("{}", err),
+    }
+}
@@ -0,0 +1,45 @@
+use std::io::{Read, Write};
+
+use crate::{Connection, Error, Event, GameInfo, Move};
+
+/// A strategy that can be plugged into a runner.
+pub trait Bot {
+    /// Called when a new game starts, with the game info if the connection
+    /// asked for it. Bots that forget the last game can reset themselves here.
+    fn new_game(&mut self, _info: Option<&GameInfo>) {}
+    /// Chooses the next move.
+    fn choose(&mut self) -> Move;
+    /// Called with the move of the opponent after each round.
+    fn observe(&mut self, _opponent: Move) {}
+}
+
+impl<B: Bot + ?Sized> Bot for Box<B> {
+    fn new_game(&mut self, info: Option<&GameInfo>) {
+        (**self).new_game(info)
+    }
+    fn choose(&mut self) -> Move {
+        (**self).choose()
+    }
+    fn observe(&mut self, opponent: Move) {
+        (**self).observe(opponent)
+    }
+}
+
+/// Plays games with the bot until the server shuts the connection down.
+pub fn run<S: Read + Write, B: Bot>(connection: &mut Connection<S>, bot: &mut B) -> Result<(), Error> {
+    loop {
+        match connection.next_event()? {
+            Event::NewGame(info) => {
+                bot.new_game(info.as_ref());
+                connection.send_move(bot.choose())?;
+            },
+            Event::OpponentMoved(m) => {
+                bot.observe(m);
+                connection.send_move(bot.choose())?;
+            },
+            Event::GameEnded(m) => bot.observe(m),
+            Event::Aborted => {},
+            Event::Shutdown => return Ok(()),
+        }
+    }
+}
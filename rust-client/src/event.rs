@@ -0,0 +1,219 @@
+use std::error;
+use std::fmt;
+use std::io::Error as IoError;
+
+use crate::handshake::NameError;
+
+/// A move. Rulesets other than the usual one use other lowercase letters.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Move {
+    Rock,
+    Paper,
+    Scissor,
+    Other(u8),
+}
+impl Move {
+    /// Decodes a move. The letters n, x and e are messages, not moves.
+    pub fn from_byte(c: u8) -> Option<Move> {
+        match c {
+            b'r' => Some(Move::Rock),
+            b'p' => Some(Move::Paper),
+            b's' => Some(Move::Scissor),
+            b'n' | b'x' | b'e' => None,
+            c if c.is_ascii_lowercase() => Some(Move::Other(c)),
+            _ => None,
+        }
+    }
+    pub fn to_byte(self) -> u8 {
+        match self {
+            Move::Rock => b'r',
+            Move::Paper => b'p',
+            Move::Scissor => b's',
+            Move::Other(c) => c,
+        }
+    }
+}
+
+/// The rules of a game.
+#[derive(Clone,Debug,PartialEq)]
+pub struct Rules {
+    pub name: String,
+    pub moves: Vec<Move>,
+    /// The points scored by move `a` against move `b` are at `a*moves+b`.
+    pub payoff: Vec<i32>,
+}
+
+/// Sent at the start of each game to clients that asked for the `rules` or
+/// `seed` capabilities.
+#[derive(Clone,Debug,PartialEq)]
+pub struct GameInfo {
+    /// The rules, if the client asked for them.
+    pub rules: Option<Rules>,
+    /// The seed of the game, if the client asked for it. Both bots are given
+    /// the same seed.
+    pub seed: Option<u64>,
+}
+impl GameInfo {
+    /// Parses a line such as
+    /// `rules=rps moves=rps payoff=0,0,1,1,0,0,0,1,0 seed=42`.
+    pub fn parse(line: &str) -> Result<GameInfo, Error> {
+        let invalid = || Error::InvalidGameInfo(String::from(line));
+        let mut name = None;
+        let mut moves = None;
+        let mut payoff = None;
+        let mut seed = None;
+        for word in line.split_whitespace() {
+            let i = word.find('=').ok_or_else(invalid)?;
+            let value = &word[i+1..];
+            match &word[..i] {
+                "rules" => name = Some(String::from(value)),
+                "moves" => {
+                    moves = Some(value.bytes()
+                        .map(Move::from_byte)
+                        .collect::<Option<Vec<Move>>>()
+                        .ok_or_else(invalid)?);
+                },
+                "payoff" => {
+                    payoff = Some(value.split(',')
+                        .map(|score| score.parse::<i32>())
+                        .collect::<Result<Vec<i32>, _>>()
+                        .map_err(|_| invalid())?);
+                },
+                "seed" => seed = Some(value.parse::<u64>().map_err(|_| invalid())?),
+                _ => {},
+            }
+        }
+        let rules = match (name, moves, payoff) {
+            (Some(name), Some(moves), Some(payoff)) => {
+                if payoff.len() != moves.len() * moves.len() {
+                    return Err(invalid());
+                }
+                Some(Rules { name, moves, payoff })
+            },
+            (None, None, None) => None,
+            _ => return Err(invalid()),
+        };
+        if rules.is_none() && seed.is_none() {
+            return Err(invalid());
+        }
+        Ok(GameInfo { rules, seed })
+    }
+}
+
+/// Something that the server told the client.
+#[derive(Clone,Debug,PartialEq)]
+pub enum Event {
+    /// A game started. The info is given if the client asked for it.
+    NewGame(Option<GameInfo>),
+    /// The opponent's move in a round, after which the game continues.
+    OpponentMoved(Move),
+    /// The opponent's move in the last round of the game.
+    GameEnded(Move),
+    /// The server aborted the game.
+    Aborted,
+    /// The server is shutting down.
+    Shutdown,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(IoError),
+    /// The server sent a byte that isn't valid at this point.
+    UnexpectedByte(u8),
+    /// The game info at the start of a game is malformed.
+    InvalidGameInfo(String),
+    /// The name was not sent, as the server would refuse it.
+    InvalidName(NameError),
+    /// The server refused the handshake, for the given reason.
+    Rejected(String),
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref err) => write!(f, "{}", err),
+            Error::UnexpectedByte(c) => write!(f, "unexpected byte {} from server", c),
+            Error::InvalidGameInfo(ref line) => write!(f, "invalid game info: {}", line),
+            Error::InvalidName(err) => write!(f, "invalid name: {}", err),
+            Error::Rejected(ref reason) => write!(f, "rejected by server: {}", reason),
+        }
+    }
+}
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref err) => Some(err),
+            Error::InvalidName(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+impl From<IoError> for Error {
+    fn from(err: IoError) -> Error {
+        Error::Io(err)
+    }
+}
+
+/// What a byte from the server means.
+pub(crate) enum Decoded {
+    Event(Event),
+    /// A game started, and the game info follows as a line.
+    NewGameWithInfo,
+    /// The game was aborted, and a sync token follows as a line, which has
+    /// to be sent back.
+    AbortedWithSync,
+}
+
+/// Turns bytes from the server into events, keeping track of whether a game
+/// is in progress.
+pub(crate) struct Decoder {
+    in_game: bool,
+    /// Whether the client asked for the game info at the start of each game.
+    info: bool,
+    /// Whether the client asked for a sync token when a game is aborted.
+    sync: bool,
+}
+impl Decoder {
+    /// Creates a decoder for a client that asked for these capabilities.
+    pub fn new(capabilities: &[&str]) -> Decoder {
+        Decoder {
+            in_game: false,
+            info: capabilities.iter().any(|&c| c == "rules" || c == "seed"),
+            sync: capabilities.contains(&"abort"),
+        }
+    }
+    pub fn decode(&mut self, c: u8) -> Result<Decoded, Error> {
+        if c == b'x' {
+            self.in_game = false;
+            return Ok(Decoded::Event(Event::Shutdown));
+        }
+        // The server may abort a game that has already ended on this side,
+        // when the opponent failed to get the last move.
+        if c == b'e' {
+            self.in_game = false;
+            if self.sync {
+                return Ok(Decoded::AbortedWithSync);
+            }
+            return Ok(Decoded::Event(Event::Aborted));
+        }
+        if !self.in_game {
+            if c != b'n' {
+                return Err(Error::UnexpectedByte(c));
+            }
+            self.in_game = true;
+            if self.info {
+                return Ok(Decoded::NewGameWithInfo);
+            }
+            return Ok(Decoded::Event(Event::NewGame(None)));
+        }
+        if let Some(m) = Move::from_byte(c) {
+            return Ok(Decoded::Event(Event::OpponentMoved(m)));
+        }
+        match Move::from_byte(c.to_ascii_lowercase()) {
+            Some(m) if c.is_ascii_uppercase() => {
+                self.in_game = false;
+                Ok(Decoded::Event(Event::GameEnded(m)))
+            },
+            _ => Err(Error::UnexpectedByte(c)),
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::error;
+use std::fmt;
+use std::io::{Error as IoError, ErrorKind};
+
+use crate::Error;
+
+/// Why a name can't be used.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum NameError {
+    Empty,
+    /// The name is longer than 255 bytes.
+    TooLong,
+    ControlCharacter,
+}
+impl fmt::Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NameError::Empty => write!(f, "the name is empty"),
+            NameError::TooLong => write!(f, "the name is longer than 255 bytes"),
+            NameError::ControlCharacter => write!(f, "the name contains a control character"),
+        }
+    }
+}
+impl error::Error for NameError {}
+
+/// Checks that the server will accept the name.
+pub fn validate_name(name: &str) -> Result<(), NameError> {
+    if name.is_empty() {
+        Err(NameError::Empty)
+    } else if name.len() > 255 {
+        Err(NameError::TooLong)
+    } else if name.chars().any(char::is_control) {
+        Err(NameError::ControlCharacter)
+    } else {
+        Ok(())
+    }
+}
+
+/// The bytes of the legacy handshake.
+pub(crate) fn legacy(name: &str) -> Result<Vec<u8>, Error> {
+    validate_name(name).map_err(Error::InvalidName)?;
+    let mut buf = vec![name.len() as u8];
+    buf.extend_from_slice(name.as_bytes());
+    buf.push(b'\n');
+    Ok(buf)
+}
+
+/// The bytes of the extended handshake, which asks for the capabilities and
+/// resumes the session of the token.
+pub(crate) fn extended(
+    name: &str,
+    token: Option<&str>,
+    capabilities: &[&str],
+) -> Result<Vec<u8>, Error> {
+    validate_name(name).map_err(Error::InvalidName)?;
+    let mut options: Vec<String> = capabilities.iter().map(|&c| String::from(c)).collect();
+    if let Some(token) = token {
+        options.push(format!("token={}", token));
+    }
+    let options = options.join(" ");
+    if options.len() > 255 {
+        return Err(Error::Io(IoError::new(ErrorKind::InvalidInput, "options too long")));
+    }
+    let mut buf = vec![name.len() as u8];
+    buf.extend_from_slice(name.as_bytes());
+    buf.push(b'?');
+    buf.push(options.len() as u8);
+    buf.extend_from_slice(options.as_bytes());
+    Ok(buf)
+}
+
+/// Parses the reply to the extended handshake, returning the session token.
+pub(crate) fn parse_reply(reply: &str) -> Result<Option<String>, Error> {
+    let mut words = reply.split_whitespace();
+    match words.next() {
+        Some("ok") => {},
+        Some("reject") => {
+            let reason = words.collect::<Vec<&str>>().join(" ");
+            return Err(Error::Rejected(reason));
+        },
+        _ => return Err(Error::Io(IoError::new(ErrorKind::InvalidData,
+                                               format!("unexpected reply: {}", reply)))),
+    }
+    Ok(words
+        .find(|word| word.starts_with("token="))
+        .map(|word| String::from(&word["token=".len()..])))
+}
+
+/// The answer to the challenge `nonce=<nonce>`, which the server sends a bot
+/// that claims a team if it has keys. The answer is a length prefixed line
+/// with the HMAC-SHA256 of `"<nonce> <name>"` under the key, in hex.
+#[cfg(feature = "auth")]
+pub(crate) fn prove(nonce: &str, name: &str, key: &[u8]) -> Vec<u8> {
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac takes keys of any length");
+    mac.update(format!("{} {}", nonce, name).as_bytes());
+    let proof: String = mac.finalize().into_bytes().iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    let mut buf = vec![proof.len() as u8];
+    buf.extend_from_slice(proof.as_bytes());
+    buf
+}
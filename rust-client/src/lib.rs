@@ -1,17 +1,66 @@
 use std::io::{Result as IoResult, Error as IoError, ErrorKind, Read, Write};
 use std::net::{ToSocketAddrs, TcpStream};
 
-pub struct Connection {
-    stream: TcpStream,
+mod bot;
+mod event;
+mod handshake;
+pub mod zoo;
+#[cfg(feature = "async")]
+mod async_client;
+
+pub use crate::bot::{Bot, run};
+pub use crate::event::{Error, Event, GameInfo, Move, Rules};
+pub use crate::handshake::{NameError, validate_name};
+use crate::event::{Decoded, Decoder};
+#[cfg(feature = "async")]
+pub use crate::async_client::{AsyncConnection, run_async};
+
+/// A connection to the server. The stream is a `TcpStream` unless it was
+/// made with `from_stream`.
+pub struct Connection<S = TcpStream> {
+    stream: S,
+    token: Option<String>,
+    decoder: Decoder,
 }
 
-impl Connection {
+impl<S: Read + Write> Connection<S> {
+    /// Wraps a stream on which the handshake has already been made, with the
+    /// capabilities that were asked for.
+    pub fn from_stream(stream: S, capabilities: &[&str]) -> Connection<S> {
+        Connection {
+            stream,
+            token: None,
+            decoder: Decoder::new(capabilities),
+        }
+    }
+    /// Waits for the next message from the server.
+    pub fn next_event(&mut self) -> Result<Event, Error> {
+        let c = self.next_char()?;
+        match self.decoder.decode(c)? {
+            Decoded::Event(event) => Ok(event),
+            Decoded::NewGameWithInfo => {
+                let line = read_line(&mut self.stream)?;
+                Ok(Event::NewGame(Some(GameInfo::parse(&line)?)))
+            },
+            Decoded::AbortedWithSync => {
+                let token = read_line(&mut self.stream)?;
+                let mut reply = vec![token.len() as u8];
+                reply.extend_from_slice(token.as_bytes());
+                self.stream.write_all(&reply)?;
+                self.stream.flush()?;
+                Ok(Event::Aborted)
+            },
+        }
+    }
+    pub fn send_move(&mut self, m: Move) -> IoResult<()> {
+        self.send_byte(m.to_byte())
+    }
     pub fn next_char(&mut self) -> IoResult<u8> {
         loop {
             let mut buf = [0];
             self.stream.read_exact(&mut buf)?;
-            if buf[0] == (' ' as u8) {
-                self.stream.write(&[' ' as u8])?;
+            if buf[0] == b' ' {
+                self.stream.write_all(b" ")?;
                 self.stream.flush()?;
             } else {
                 return Ok(buf[0]);
@@ -20,27 +69,107 @@ impl Connection {
     }
     pub fn send_byte(&mut self, c: u8) -> IoResult<()> {
         let buf = [c];
-        self.stream.write(&buf)?;
+        self.stream.write_all(&buf)?;
         self.stream.flush()?;
         Ok(())
     }
-    pub fn connect<A: ToSocketAddrs>(addr: A, name: &str) -> IoResult<Connection> {
-        if name.len() > 255 {
-            panic!("Name longer than 255 bytes.");
-        }
+    /// The session token given by the server, if connected with
+    /// `connect_session`.
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+}
+
+impl Connection {
+    /// Connects using the legacy handshake. The server doesn't reply, so a
+    /// refused connection is only noticed when it is closed.
+    pub fn connect<A: ToSocketAddrs>(addr: A, name: &str) -> Result<Connection, Error> {
+        let handshake = handshake::legacy(name)?;
         let mut conn = TcpStream::connect(addr)?;
-        let mut buf = [0u8; 258];
-        buf[0] = name.len() as u8;
-        for (i, b) in name.as_bytes().iter().cloned().enumerate() {
-            buf[i+1] = b;
-        }
-        buf[name.len() + 1] = '\n' as u8;
-        conn.write(&buf[0..name.len()+2])?;
+        conn.write_all(&handshake)?;
         conn.flush()?;
         Ok(Connection {
             stream: conn,
+            token: None,
+            decoder: Decoder::new(&[]),
         })
     }
+    /// Connects using the extended handshake, which gives the connection a
+    /// session token. Passing the token of an earlier connection when
+    /// reconnecting lets the server know it is the same bot. If the server
+    /// refuses the bot, the reason is given in `Error::Rejected`.
+    pub fn connect_session<A: ToSocketAddrs>(
+        addr: A,
+        name: &str,
+        token: Option<&str>,
+    ) -> Result<Connection, Error> {
+        Connection::connect_session_with(addr, name, token, &[])
+    }
+    /// Connects using the extended handshake, asking for the given
+    /// capabilities. With `rules`, each new game comes with its rules, and with
+    /// `seed`, with a seed shared by both bots. With `abort`, an aborted game
+    /// is acknowledged, so a late move can't end up in the next game.
+    pub fn connect_session_with<A: ToSocketAddrs>(
+        addr: A,
+        name: &str,
+        token: Option<&str>,
+        capabilities: &[&str],
+    ) -> Result<Connection, Error> {
+        let handshake = handshake::extended(name, token, capabilities)?;
+        let mut conn = TcpStream::connect(addr)?;
+        conn.write_all(&handshake)?;
+        conn.flush()?;
+        let token = handshake::parse_reply(&read_line(&mut conn)?)?;
+        Ok(Connection {
+            stream: conn,
+            token,
+            decoder: Decoder::new(capabilities),
+        })
+    }
+    /// Connects like `connect_session_with`, and authenticates as a bot of
+    /// the team with its shared key. Only authenticated bots are rated on
+    /// servers that have keys, and servers without keys accept the bot
+    /// without a challenge. A wrong key is rejected with `bad-auth`.
+    #[cfg(feature = "auth")]
+    pub fn connect_authenticated<A: ToSocketAddrs>(
+        addr: A,
+        name: &str,
+        token: Option<&str>,
+        capabilities: &[&str],
+        team: &str,
+        key: &[u8],
+    ) -> Result<Connection, Error> {
+        let team = format!("team={}", team);
+        let mut options = capabilities.to_vec();
+        options.push(&team);
+        let handshake = handshake::extended(name, token, &options)?;
+        let mut conn = TcpStream::connect(addr)?;
+        conn.write_all(&handshake)?;
+        conn.flush()?;
+        let mut reply = read_line(&mut conn)?;
+        // A server without keys replies right away.
+        if let Some(nonce) = reply.strip_prefix("nonce=") {
+            conn.write_all(&handshake::prove(nonce, name, key))?;
+            conn.flush()?;
+            reply = read_line(&mut conn)?;
+        }
+        let token = handshake::parse_reply(&reply)?;
+        Ok(Connection {
+            stream: conn,
+            token,
+            decoder: Decoder::new(capabilities),
+        })
+    }
+}
+
+/// Reads a line sent by the server, which is prefixed by its length.
+fn read_line<R: Read>(stream: &mut R) -> IoResult<String> {
+    let mut len_buf = [0];
+    stream.read_exact(&mut len_buf)?;
+    let mut line = vec![0; usize::from(len_buf[0])];
+    stream.read_exact(&mut line)?;
+    String::from_utf8(line)
+        .map_err(|_| IoError::new(ErrorKind::InvalidData, "line invalid utf8"))
 }
 
 
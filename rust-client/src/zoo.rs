@@ -0,0 +1,463 @@
+//! Reference bots for the usual three moves. Moves are handled as indexes,
+//! where move `i` beats move `(i + 2) % 3` and loses to move `(i + 1) % 3`.
+
+use crate::{Bot, GameInfo, Move};
+
+/// The names of the bots that `by_name` knows.
+pub const NAMES: &[&str] = &[
+    "rock", "paper", "scissor", "cycle", "random", "frequency", "wsls", "copy", "beatlast",
+    "debruijn", "iocaine", "markov", "antimarkov",
+];
+
+/// Creates a bot by name. Bots that use randomness are seeded with `seed`.
+pub fn by_name(name: &str, seed: u64) -> Option<Box<dyn Bot + Send>> {
+    let bot: Box<dyn Bot + Send> = match name {
+        "rock" => Box::new(Constant(Move::Rock)),
+        "paper" => Box::new(Constant(Move::Paper)),
+        "scissor" => Box::new(Constant(Move::Scissor)),
+        "cycle" => Box::new(Cycle::default()),
+        "random" => Box::new(UniformRandom::new(seed)),
+        "frequency" => Box::new(Frequency::default()),
+        "wsls" => Box::new(WinStayLoseShift::default()),
+        "copy" => Box::new(Copy::default()),
+        "beatlast" => Box::new(BeatLast::default()),
+        "debruijn" => Box::new(DeBruijn::new(4)),
+        "iocaine" => Box::new(Iocaine::default()),
+        "markov" => Box::new(Maximizer::new(markov_predictor())),
+        "antimarkov" => Box::new(anti_markov()),
+        _ => return None,
+    };
+    Some(bot)
+}
+
+const MOVES: [Move; 3] = [Move::Rock, Move::Paper, Move::Scissor];
+
+fn index(m: Move) -> usize {
+    match m {
+        Move::Rock => 0,
+        Move::Paper => 1,
+        Move::Scissor => 2,
+        Move::Other(_) => 0,
+    }
+}
+
+/// The move that beats move `i`.
+fn beater(i: usize) -> usize {
+    (i + 1) % 3
+}
+
+/// A small seeded xorshift generator, so bots can be reproduced.
+#[derive(Clone)]
+pub struct Rng {
+    state: u64,
+}
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: (seed ^ 0x9e37_79b9_7f4a_7c15) | 1 }
+    }
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+    /// A number in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+/// Always plays the same move.
+pub struct Constant(pub Move);
+impl Bot for Constant {
+    fn choose(&mut self) -> Move {
+        self.0
+    }
+}
+
+/// Plays rock, paper and scissor in turn.
+#[derive(Default)]
+pub struct Cycle {
+    next: usize,
+}
+impl Bot for Cycle {
+    fn choose(&mut self) -> Move {
+        let m = MOVES[self.next];
+        self.next = (self.next + 1) % 3;
+        m
+    }
+}
+
+/// Plays uniformly random moves. If the server sends a seed, it is mixed
+/// with the bot's own seed at the start of each game, so the game can be
+/// replayed while two random bots still play differently.
+pub struct UniformRandom {
+    seed: u64,
+    rng: Rng,
+}
+impl UniformRandom {
+    pub fn new(seed: u64) -> UniformRandom {
+        UniformRandom { seed, rng: Rng::new(seed) }
+    }
+}
+impl Bot for UniformRandom {
+    fn new_game(&mut self, info: Option<&GameInfo>) {
+        if let Some(seed) = info.and_then(|info| info.seed) {
+            self.rng = Rng::new(seed ^ self.seed);
+        }
+    }
+    fn choose(&mut self) -> Move {
+        MOVES[self.rng.below(3)]
+    }
+}
+
+/// Beats the move the opponent has played the most.
+#[derive(Default)]
+pub struct Frequency {
+    counts: [usize; 3],
+}
+impl Bot for Frequency {
+    fn choose(&mut self) -> Move {
+        let most = (0..3).max_by_key(|&i| self.counts[i]).unwrap();
+        MOVES[beater(most)]
+    }
+    fn observe(&mut self, opponent: Move) {
+        self.counts[index(opponent)] += 1;
+    }
+}
+
+/// Keeps its move after a win, and otherwise switches to the move that beats
+/// its own last move.
+#[derive(Default)]
+pub struct WinStayLoseShift {
+    last: usize,
+}
+impl Bot for WinStayLoseShift {
+    fn new_game(&mut self, _info: Option<&GameInfo>) {
+        self.last = 0;
+    }
+    fn choose(&mut self) -> Move {
+        MOVES[self.last]
+    }
+    fn observe(&mut self, opponent: Move) {
+        let won = self.last == beater(index(opponent));
+        if !won {
+            self.last = beater(self.last);
+        }
+    }
+}
+
+/// Plays the opponent's last move.
+#[derive(Default)]
+pub struct Copy {
+    last: usize,
+}
+impl Bot for Copy {
+    fn choose(&mut self) -> Move {
+        MOVES[self.last]
+    }
+    fn observe(&mut self, opponent: Move) {
+        self.last = index(opponent);
+    }
+}
+
+/// Plays the move that beats the opponent's last move.
+#[derive(Default)]
+pub struct BeatLast {
+    last: usize,
+}
+impl Bot for BeatLast {
+    fn choose(&mut self) -> Move {
+        MOVES[beater(self.last)]
+    }
+    fn observe(&mut self, opponent: Move) {
+        self.last = index(opponent);
+    }
+}
+
+/// Plays a de Bruijn sequence, in which every sequence of `order` moves
+/// appears once, so the moves have no short patterns.
+pub struct DeBruijn {
+    sequence: Vec<usize>,
+    next: usize,
+}
+impl DeBruijn {
+    pub fn new(order: usize) -> DeBruijn {
+        let mut sequence = Vec::new();
+        let mut a = vec![0; order + 1];
+        de_bruijn(1, 1, order, &mut a, &mut sequence);
+        DeBruijn { sequence, next: 0 }
+    }
+}
+/// The recursive construction of a de Bruijn sequence over three symbols.
+fn de_bruijn(t: usize, p: usize, order: usize, a: &mut Vec<usize>, sequence: &mut Vec<usize>) {
+    if t > order {
+        if order.is_multiple_of(p) {
+            sequence.extend_from_slice(&a[1..=p]);
+        }
+    } else {
+        a[t] = a[t - p];
+        de_bruijn(t + 1, p, order, a, sequence);
+        for j in a[t - p] + 1..3 {
+            a[t] = j;
+            de_bruijn(t + 1, t, order, a, sequence);
+        }
+    }
+}
+impl Bot for DeBruijn {
+    fn choose(&mut self) -> Move {
+        let m = MOVES[self.sequence[self.next]];
+        self.next = (self.next + 1) % self.sequence.len();
+        m
+    }
+}
+
+/// A simplified Iocaine Powder. A few predictors guess the next move of each
+/// player, and each guess is played at three levels of second-guessing. The
+/// move of the strategy that has scored best recently is played.
+#[derive(Default)]
+pub struct Iocaine {
+    /// The moves so far as `(own, opponent)`.
+    history: Vec<(usize, usize)>,
+    /// How often each player has played each move.
+    counts: [[usize; 3]; 2],
+    /// The decayed score of each strategy.
+    scores: Vec<f64>,
+    /// The move each strategy suggested this round.
+    suggestions: Vec<usize>,
+}
+impl Iocaine {
+    /// How fast old results are forgotten.
+    const DECAY: f64 = 0.95;
+    /// How far back the history is searched for matches.
+    const WINDOW: usize = 1000;
+
+    /// Predicts the next move of one player: the most frequent move, and the
+    /// move that followed the longest earlier match of the recent history.
+    fn predictions(&self, player: usize) -> [usize; 2] {
+        let get = |round: &(usize, usize)| if player == 0 { round.0 } else { round.1 };
+        let counts = self.counts[player];
+        let frequent = (0..3).max_by_key(|&i| counts[i]).unwrap();
+        let n = self.history.len();
+        let mut best = (0, self.history.last().map(get).unwrap_or(0));
+        for end in n.saturating_sub(Iocaine::WINDOW).max(1)..n {
+            let len = (0..end.min(20))
+                .take_while(|&k| self.history[end - 1 - k] == self.history[n - 1 - k])
+                .count();
+            if len > best.0 {
+                best = (len, get(&self.history[end]));
+            }
+        }
+        [frequent, best.1]
+    }
+    fn suggest(&mut self) {
+        let mut suggestions = Vec::new();
+        for &other in &self.predictions(1) {
+            for level in 0..3 {
+                suggestions.push((other + 1 + level) % 3);
+            }
+        }
+        for &own in &self.predictions(0) {
+            for level in 0..3 {
+                suggestions.push((own + 2 + level) % 3);
+            }
+        }
+        if self.scores.len() != suggestions.len() {
+            self.scores = vec![0.; suggestions.len()];
+        }
+        self.suggestions = suggestions;
+    }
+}
+impl Bot for Iocaine {
+    fn choose(&mut self) -> Move {
+        self.suggest();
+        let best = (0..self.scores.len())
+            .max_by(|&a, &b| self.scores[a].partial_cmp(&self.scores[b]).unwrap())
+            .unwrap();
+        let m = self.suggestions[best];
+        self.history.push((m, 0));
+        MOVES[m]
+    }
+    fn observe(&mut self, opponent: Move) {
+        let opponent = index(opponent);
+        if let Some(last) = self.history.last_mut() {
+            last.1 = opponent;
+            self.counts[0][last.0] += 1;
+            self.counts[1][opponent] += 1;
+        }
+        for (score, &m) in self.scores.iter_mut().zip(&self.suggestions) {
+            let result = if m == beater(opponent) {
+                1.
+            } else if opponent == beater(m) {
+                -1.
+            } else {
+                0.
+            };
+            *score = *score * Iocaine::DECAY + result;
+        }
+    }
+}
+
+/// Predicts the probability of each move of the opponent.
+pub trait Predictor: Send {
+    fn prob(&self, m: usize) -> f64;
+    /// Called after each round with the moves of both players.
+    fn update(&mut self, own: usize, other: usize);
+}
+
+/// Predicts the opponent's move from the moves that followed earlier times
+/// the last `length` rounds were played, remembering `persistence` rounds.
+pub struct MarkovChain {
+    length: usize,
+    persistence: usize,
+    /// The rounds as `(own, other)`, the most recent first.
+    memory: Vec<(usize, usize)>,
+    table: [usize; 3],
+}
+impl MarkovChain {
+    pub fn new(length: usize, persistence: usize) -> MarkovChain {
+        MarkovChain {
+            length,
+            persistence,
+            memory: Vec::new(),
+            table: [0; 3],
+        }
+    }
+    fn count(&mut self) {
+        let memory = &self.memory;
+        let init = &memory[..self.length.min(memory.len())];
+        self.table = [0; 3];
+        for k in 0..memory.len() {
+            let window = &memory[k..(k + self.length + 1).min(memory.len())];
+            if window.len() > 1 && init.starts_with(&window[1..]) {
+                self.table[window[0].1] += 1;
+            }
+        }
+    }
+}
+impl Predictor for MarkovChain {
+    fn prob(&self, m: usize) -> f64 {
+        let total: usize = self.table.iter().sum();
+        (self.table[m] as f64 + 1.) / (total as f64 + 3.)
+    }
+    fn update(&mut self, own: usize, other: usize) {
+        self.memory.insert(0, (own, other));
+        self.memory.truncate(self.persistence);
+        self.count();
+    }
+}
+
+/// Mixes predictors, weighting each by how well it predicted recent moves.
+pub struct Ensemble {
+    models: Vec<(Box<dyn Predictor>, f64)>,
+    decay: f64,
+}
+impl Ensemble {
+    pub fn new(predictors: Vec<Box<dyn Predictor>>, decay: f64) -> Ensemble {
+        let weight = 1. / predictors.len() as f64;
+        Ensemble {
+            models: predictors.into_iter().map(|model| (model, weight)).collect(),
+            decay,
+        }
+    }
+}
+impl Predictor for Ensemble {
+    fn prob(&self, m: usize) -> f64 {
+        self.models.iter().map(|&(ref model, weight)| model.prob(m) * weight).sum()
+    }
+    fn update(&mut self, own: usize, other: usize) {
+        let predictions: Vec<f64> = self.models.iter()
+            .map(|&(ref model, weight)| model.prob(other) * weight)
+            .collect();
+        let likelihood: f64 = predictions.iter().sum();
+        let escort: Vec<f64> = predictions.iter()
+            .map(|p| (p / likelihood).powf(self.decay))
+            .collect();
+        let sum: f64 = escort.iter().sum();
+        for ((model, weight), p) in self.models.iter_mut().zip(escort) {
+            model.update(own, other);
+            *weight = p / sum;
+        }
+    }
+}
+
+/// Plays the move with the best expected score against a predictor.
+pub struct Maximizer {
+    model: Box<dyn Predictor>,
+}
+impl Maximizer {
+    pub fn new(model: Box<dyn Predictor>) -> Maximizer {
+        Maximizer { model }
+    }
+    fn best(&self) -> usize {
+        let value = |m: usize| self.model.prob((m + 2) % 3) - self.model.prob(beater(m));
+        (0..3).max_by(|&a, &b| value(a).partial_cmp(&value(b)).unwrap()).unwrap()
+    }
+}
+impl Bot for Maximizer {
+    fn choose(&mut self) -> Move {
+        MOVES[self.best()]
+    }
+    fn observe(&mut self, opponent: Move) {
+        let own = self.best();
+        self.model.update(own, index(opponent));
+    }
+}
+
+/// Predicts that the opponent plays like a maximizer would in its place.
+struct MaximizerPredictor {
+    strategy: Maximizer,
+}
+impl Predictor for MaximizerPredictor {
+    fn prob(&self, m: usize) -> f64 {
+        if self.strategy.best() == m { 1. } else { 0. }
+    }
+    fn update(&mut self, own: usize, other: usize) {
+        self.strategy.model.update(other, own);
+    }
+}
+
+/// The Markov chains used by the markov bot.
+pub fn markov_predictor() -> Box<dyn Predictor> {
+    Box::new(Ensemble::new(vec![
+        Box::new(MarkovChain::new(2, 100)),
+        Box::new(MarkovChain::new(1, 100)),
+        Box::new(MarkovChain::new(1, 20)),
+        Box::new(MarkovChain::new(3, 1000)),
+    ], 0.9))
+}
+
+/// Expects the opponent to either follow a Markov model, or to be a markov
+/// bot itself.
+fn anti_markov() -> Maximizer {
+    let markov_bot = MaximizerPredictor {
+        strategy: Maximizer::new(markov_predictor()),
+    };
+    Maximizer::new(Box::new(Ensemble::new(vec![markov_predictor(), Box::new(markov_bot)], 0.9)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The moves the bot plays against a fixed sequence of moves.
+    fn replies(bot: &mut dyn Bot, opponent: &[Move]) -> Vec<Move> {
+        bot.new_game(None);
+        opponent.iter().map(|&m| {
+            let reply = bot.choose();
+            bot.observe(m);
+            reply
+        }).collect()
+    }
+
+    #[test]
+    fn wsls_shifts_only_after_a_loss_or_tie() {
+        let opponent = [Move::Scissor, Move::Paper, Move::Paper, Move::Rock];
+        let wsls = replies(&mut WinStayLoseShift::default(), &opponent);
+        // Wins with rock and stays, loses to paper and shifts to paper, ties
+        // and shifts to scissor.
+        assert_eq!(wsls, [Move::Rock, Move::Rock, Move::Paper, Move::Scissor]);
+        let beatlast = replies(&mut BeatLast::default(), &opponent);
+        assert_ne!(wsls, beatlast);
+    }
+}
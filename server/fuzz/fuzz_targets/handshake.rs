@@ -0,0 +1,16 @@
+//! Reads a handshake from untrusted bytes. It must fail with an error, not a
+//! panic, when they are malformed.
+
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use server::client::read_handshake;
+
+fuzz_target!(|data: &[u8]| {
+    let mut stream = Cursor::new(data);
+    if let Ok((name, _)) = read_handshake(&mut stream) {
+        assert!(name.len() < data.len());
+    }
+});
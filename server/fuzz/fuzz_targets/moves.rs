@@ -0,0 +1,22 @@
+//! Decodes moves with every builtin ruleset on the server and in the client.
+//! A byte that decodes to a move must encode back to the same byte.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_client::Move;
+use server::rules::Ruleset;
+
+fuzz_target!(|data: &[u8]| {
+    let rulesets = Ruleset::builtin();
+    for &byte in data {
+        for rules in &rulesets {
+            if let Ok(m) = rules.parse(byte) {
+                assert_eq!(rules.symbol(m), byte);
+            }
+        }
+        if let Some(m) = Move::from_byte(byte) {
+            assert_eq!(m.to_byte(), byte);
+        }
+    }
+});
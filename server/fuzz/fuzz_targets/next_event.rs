@@ -0,0 +1,42 @@
+//! Feeds untrusted bytes to a client connection as if the server sent them.
+//! The bits of the first byte pick the capabilities. Every call must consume
+//! input, so the loop ends with an error once the input runs out.
+
+#![no_main]
+
+use std::io::{Read, Result as IoResult, Write};
+
+use libfuzzer_sys::fuzz_target;
+use rust_client::Connection;
+
+/// Reads from a slice and discards what is written.
+struct Duplex<'a> {
+    input: &'a [u8],
+}
+impl Read for Duplex<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        self.input.read(buf)
+    }
+}
+impl Write for Duplex<'_> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let (&flags, input) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let capabilities: Vec<&str> = ["rules", "seed", "abort"].iter()
+        .enumerate()
+        .filter(|&(i, _)| flags & (1 << i) != 0)
+        .map(|(_, &capability)| capability)
+        .collect();
+    let mut connection = Connection::from_stream(Duplex { input }, &capabilities);
+    while connection.next_event().is_ok() {}
+});
@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::io::{Result as IoResult, ErrorKind};
+use std::path::Path;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The shared secret of each team. Bots of a team authenticate by sending
+/// the HMAC-SHA256 of `"<nonce> <name>"` under the key, in hex.
+#[derive(Clone,Default)]
+pub struct Keys {
+    keys: HashMap<String, Vec<u8>>,
+}
+impl Keys {
+    /// Loads a keyfile with a team name and a key on each line, separated
+    /// by whitespace. Lines starting with # are skipped. A missing file
+    /// gives no keys, which turns authentication off.
+    pub fn load(path: &Path) -> IoResult<Keys> {
+        let contents = match read_to_string(path) {
+            Ok(contents) => contents,
+            Err(ref err) if err.kind() == ErrorKind::NotFound => return Ok(Keys::default()),
+            Err(err) => return Err(err),
+        };
+        let mut keys = HashMap::new();
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            if let (Some(team), Some(key)) = (words.next(), words.next()) {
+                keys.insert(String::from(team), key.as_bytes().to_vec());
+            }
+        }
+        Ok(Keys { keys })
+    }
+    /// Returns true if no team has a key, in which case every bot is rated.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+    /// Checks the proof sent by a bot of the team.
+    pub fn verify(&self, team: &str, nonce: &str, name: &str, proof: &str) -> bool {
+        let key = match self.keys.get(team) {
+            Some(key) => key,
+            None => return false,
+        };
+        let proof = match from_hex(proof) {
+            Some(proof) => proof,
+            None => return false,
+        };
+        let mut mac = HmacSha256::new_from_slice(key).expect("hmac takes keys of any length");
+        mac.update(format!("{} {}", nonce, name).as_bytes());
+        mac.verify_slice(&proof).is_ok()
+    }
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i+2], 16).ok())
+        .collect()
+}
@@ -0,0 +1,103 @@
+use std::collections::BTreeSet;
+use std::fs::{read_to_string, write};
+use std::io::{Result as IoResult, ErrorKind};
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+
+/// Banned names and addresses. They are saved to a file, one per line, when
+/// the list changes.
+#[derive(Default)]
+pub struct Bans {
+    path: Option<PathBuf>,
+    names: BTreeSet<String>,
+    ips: BTreeSet<IpAddr>,
+}
+impl Bans {
+    /// Loads the list from the file, which doesn't have to exist yet.
+    pub fn load(path: PathBuf) -> IoResult<Bans> {
+        let mut bans = Bans::default();
+        match read_to_string(&path) {
+            Ok(contents) => {
+                for line in contents.lines().filter(|line| !line.is_empty()) {
+                    bans.insert(line);
+                }
+            },
+            Err(ref err) if err.kind() == ErrorKind::NotFound => {},
+            Err(err) => return Err(err),
+        }
+        bans.path = Some(path);
+        Ok(bans)
+    }
+    fn save(&self) -> IoResult<()> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        let mut contents = String::new();
+        for entry in self.list() {
+            contents.push_str(&entry);
+            contents.push('\n');
+        }
+        write(path, contents)
+    }
+    /// Adds an address if `entry` is one, and a name otherwise.
+    fn insert(&mut self, entry: &str) -> bool {
+        match entry.parse::<IpAddr>() {
+            Ok(ip) => self.ips.insert(canonical(ip)),
+            Err(_) => self.names.insert(String::from(entry)),
+        }
+    }
+    /// Bans a name or an address. Returns false if it was already banned.
+    pub fn ban(&mut self, entry: &str) -> IoResult<bool> {
+        let added = self.insert(entry);
+        if added {
+            self.save()?;
+        }
+        Ok(added)
+    }
+    /// Lifts a ban. Returns false if there was none.
+    pub fn unban(&mut self, entry: &str) -> IoResult<bool> {
+        let removed = match entry.parse::<IpAddr>() {
+            Ok(ip) => self.ips.remove(&canonical(ip)),
+            Err(_) => self.names.remove(entry),
+        };
+        if removed {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+    pub fn is_name_banned(&self, name: &str) -> bool {
+        self.names.contains(name)
+    }
+    pub fn is_ip_banned(&self, ip: IpAddr) -> bool {
+        self.ips.contains(&canonical(ip))
+    }
+    /// Returns true if the client with this name and address is banned. The
+    /// address is the one given to `Client::new`.
+    pub fn is_banned(&self, name: &str, addr: &str) -> bool {
+        self.is_name_banned(name) || ip_of(addr).is_some_and(|ip| self.is_ip_banned(ip))
+    }
+    /// The banned addresses, followed by the banned names.
+    pub fn list(&self) -> Vec<String> {
+        self.ips.iter().map(|ip| ip.to_string())
+            .chain(self.names.iter().cloned())
+            .collect()
+    }
+}
+
+/// The IP address in the address of a client, if it connected over TCP.
+pub fn ip_of(addr: &str) -> Option<IpAddr> {
+    addr.parse::<SocketAddr>().ok().map(|addr| addr.ip())
+}
+
+/// Turns IPv4 addresses mapped into IPv6 back into IPv4, as clients of the
+/// dual stack listener have such addresses.
+pub fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => ip,
+        },
+        IpAddr::V4(_) => ip,
+    }
+}
@@ -0,0 +1,423 @@
+use std::fmt;
+use std::io::{Result as IoResult};
+use std::time::{Duration, Instant};
+use rand::{Rng, SeedableRng, thread_rng};
+use rand::rngs::StdRng;
+use statrs::function::erf::erf;
+
+use client::*;
+use metrics::{METRICS, inc};
+use rules::{Move, GameOutcome, Ruleset};
+
+/// What to do when a bot times out or sends a byte that isn't a move.
+#[derive(Clone,Copy,Debug)]
+pub enum Policy {
+    /// The bot loses the round.
+    ForfeitRound,
+    /// The bot loses the match.
+    ForfeitMatch,
+    /// A random move is played in place of the missing one.
+    RandomMove,
+    /// The bot loses the round, and loses the match once it has this many
+    /// violations.
+    Disqualify(usize),
+}
+impl Policy {
+    /// Returns true if a bot with this many violations loses the match.
+    fn forfeits_match(self, violations: usize) -> bool {
+        match self {
+            Policy::ForfeitMatch => violations > 0,
+            Policy::Disqualify(limit) => violations >= limit,
+            Policy::ForfeitRound | Policy::RandomMove => false,
+        }
+    }
+}
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Policy::ForfeitRound => write!(f, "forfeit the round"),
+            Policy::ForfeitMatch => write!(f, "forfeit the match"),
+            Policy::RandomMove => write!(f, "play a random move"),
+            Policy::Disqualify(limit) => {
+                write!(f, "forfeit the round, disqualify after {} violations", limit)
+            },
+        }
+    }
+}
+
+/// The settings of a match.
+#[derive(Clone)]
+pub struct MatchConfig {
+    /// The number of games, each starting with a new game message.
+    pub games: usize,
+    /// The number of rounds in each game.
+    pub rounds: usize,
+    pub policy: Policy,
+    pub rules: Ruleset,
+    /// The probability that a move is replaced by a random one before it is
+    /// scored.
+    pub noise: f64,
+    /// Whether the opponent is told the move after noise, rather than the one
+    /// that was sent.
+    pub noisy_feedback: bool,
+    /// The seed of all randomness in the match, or `None` to pick a new one
+    /// for each match.
+    pub seed: Option<u64>,
+}
+impl MatchConfig {
+    pub fn new(rounds: usize, policy: Policy) -> MatchConfig {
+        MatchConfig {
+            games: 1,
+            rounds,
+            policy,
+            rules: Ruleset::builtin().remove(0),
+            noise: 0.,
+            noisy_feedback: true,
+            seed: None,
+        }
+    }
+    /// Applies an option given as `key=value` to a command.
+    pub fn set_option(
+        &mut self,
+        key: &str,
+        value: &str,
+        rulesets: &[Ruleset],
+    ) -> Result<(), String> {
+        match key {
+            "rules" => {
+                self.rules = rulesets.iter()
+                    .find(|rules| rules.name == value)
+                    .ok_or_else(|| format!("There is no ruleset called {}.", value))?
+                    .clone();
+            },
+            "rounds" => {
+                self.rounds = match value.parse::<usize>() {
+                    Ok(rounds) if rounds > 0 => rounds,
+                    _ => return Err(format!("{} is not a number of rounds.", value)),
+                };
+            },
+            "games" => {
+                self.games = match value.parse::<usize>() {
+                    Ok(games) if games > 0 => games,
+                    _ => return Err(format!("{} is not a number of games.", value)),
+                };
+            },
+            "seed" => {
+                self.seed = match value.parse::<u64>() {
+                    Ok(seed) => Some(seed),
+                    Err(_) => return Err(format!("{} is not a seed.", value)),
+                };
+            },
+            "noise" => {
+                self.noise = match value.parse::<f64>() {
+                    Ok(p) if (0. ..=1.).contains(&p) => p,
+                    _ => return Err(format!("{} is not a probability.", value)),
+                };
+            },
+            "feedback" => {
+                self.noisy_feedback = match value {
+                    "noisy" => true,
+                    "true" => false,
+                    _ => return Err(String::from("The feedback must be noisy or true.")),
+                };
+            },
+            _ => return Err(format!("Unknown option {}.", key)),
+        }
+        Ok(())
+    }
+}
+
+/// The result of one game, or the total of a set of games.
+pub struct MatchReport {
+    /// The number of rounds that were played.
+    pub rounds: usize,
+    pub wins: [usize; 2],
+    pub ties: usize,
+    /// The points scored according to the payoff matrix of the ruleset.
+    pub score: [i64; 2],
+    /// Timeouts and illegal moves of each bot.
+    pub violations: [usize; 2],
+    pub forfeited: [bool; 2],
+    /// Moves that were changed by noise.
+    pub flips: [usize; 2],
+    pub duration: Duration,
+    /// The variance of the score difference per round under random play.
+    variance: f64,
+}
+impl MatchReport {
+    fn new(variance: f64) -> MatchReport {
+        MatchReport {
+            rounds: 0,
+            wins: [0, 0],
+            ties: 0,
+            score: [0, 0],
+            violations: [0, 0],
+            forfeited: [false, false],
+            flips: [0, 0],
+            duration: Duration::new(0, 0),
+            variance,
+        }
+    }
+    /// Adds the result of another game to this one.
+    fn add(&mut self, other: &MatchReport) {
+        self.rounds += other.rounds;
+        self.ties += other.ties;
+        self.duration += other.duration;
+        for i in 0..2 {
+            self.wins[i] += other.wins[i];
+            self.score[i] += other.score[i];
+            self.violations[i] += other.violations[i];
+            self.flips[i] += other.flips[i];
+            self.forfeited[i] |= other.forfeited[i];
+        }
+    }
+    /// Returns 0 or 1 if that bot won, and `None` on a tie. A bot that
+    /// forfeited loses to one that didn't, whatever the points. Otherwise
+    /// the bot that scored the most points wins.
+    pub fn winner(&self) -> Option<usize> {
+        if self.forfeited[0] != self.forfeited[1] {
+            Some(if self.forfeited[0] { 1 } else { 0 })
+        } else if self.score[0] > self.score[1] {
+            Some(0)
+        } else if self.score[1] > self.score[0] {
+            Some(1)
+        } else {
+            None
+        }
+    }
+    pub fn print(&self, name1: &str, name2: &str) {
+        println!("{} won {} times.", name1, self.wins[0]);
+        println!("{} won {} times.", name2, self.wins[1]);
+        println!("There were {} ties.", self.ties);
+        if self.score[0] != self.wins[0] as i64 || self.score[1] != self.wins[1] as i64 {
+            println!("{} scored {} points.", name1, self.score[0]);
+            println!("{} scored {} points.", name2, self.score[1]);
+        }
+        for (name, &violations) in [name1, name2].iter().zip(&self.violations) {
+            if violations > 0 {
+                println!("{} had {} violations.", name, violations);
+            }
+        }
+        for (name, &flips) in [name1, name2].iter().zip(&self.flips) {
+            if flips > 0 {
+                println!("{} had {} moves changed by noise.", name, flips);
+            }
+        }
+        for (name, &forfeited) in [name1, name2].iter().zip(&self.forfeited) {
+            if forfeited {
+                println!("{} forfeited the match after {} rounds.", name, self.rounds);
+            }
+        }
+        if self.rounds > 0 && self.variance > 0. {
+            let diff = (self.score[0] - self.score[1]) as f64;
+            let mean = 0f64;
+            let stddev_times_sqrt2 = (2. * self.variance * self.rounds as f64).sqrt();
+            let cdf1 = 0.5 * (1. + erf((diff - mean)/(stddev_times_sqrt2)));
+            let cdf2 = 0.5 * (1. + erf((mean - diff)/(stddev_times_sqrt2)));
+            println!("CDF1: {:.8}", cdf1);
+            println!("CDF2: {:.8}", cdf2);
+        }
+        let duration = self.duration.as_secs() as f64
+            + self.duration.subsec_millis() as f64 / 1000f64;
+        println!("Game finished in {:.2} s.", duration);
+    }
+}
+
+/// The games played in a set, in order.
+pub struct SetReport {
+    pub games: Vec<MatchReport>,
+}
+impl SetReport {
+    /// The sum of all the games.
+    pub fn total(&self) -> MatchReport {
+        let mut total = MatchReport::new(self.games[0].variance);
+        for game in &self.games {
+            total.add(game);
+        }
+        total
+    }
+    pub fn print(&self, name1: &str, name2: &str) {
+        if self.games.len() == 1 {
+            self.games[0].print(name1, name2);
+            return;
+        }
+        let mut games_won = [0, 0];
+        for (i, game) in self.games.iter().enumerate() {
+            let result = match game.winner() {
+                Some(winner) => {
+                    games_won[winner] += 1;
+                    format!("won by {}", [name1, name2][winner])
+                },
+                None => String::from("tied"),
+            };
+            println!("Game {}: {} to {} with {} ties, {}.",
+                     i + 1, game.wins[0], game.wins[1], game.ties, result);
+        }
+        println!("{} won {} games.", name1, games_won[0]);
+        println!("{} won {} games.", name2, games_won[1]);
+        println!("In total:");
+        self.total().print(name1, name2);
+    }
+}
+
+/// Plays the games of a set one after another. The set ends early if a bot
+/// forfeits the match.
+pub fn play_set(
+    bot1: &mut Client,
+    bot2: &mut Client,
+    config: &MatchConfig,
+) -> IoResult<SetReport> {
+    let seed = config.seed.unwrap_or_else(|| thread_rng().gen());
+    info!(target: "battle", "Match between {} and {} uses seed {}.", bot1.name, bot2.name, seed);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut report = SetReport { games: Vec::new() };
+    inc(&METRICS.matches_started);
+    for _ in 0..config.games {
+        let game = match long_battle(bot1, bot2, config, &mut rng) {
+            Ok(game) => game,
+            Err(err) => {
+                inc(&METRICS.matches_failed);
+                return Err(err);
+            },
+        };
+        let forfeited = game.forfeited[0] || game.forfeited[1];
+        report.games.push(game);
+        if forfeited {
+            break;
+        }
+    }
+    inc(&METRICS.matches_finished);
+    Ok(report)
+}
+
+/// Plays a game. Violations are handled according to the policy of the
+/// config, while other io errors abort the game. All random choices are
+/// taken from `rng`, and the bots are sent a seed drawn from it.
+pub fn long_battle(
+    bot1: &mut Client,
+    bot2: &mut Client,
+    config: &MatchConfig,
+    rng: &mut StdRng,
+) -> IoResult<MatchReport> {
+    let now = Instant::now();
+    let rules = &config.rules;
+    let policy = config.policy;
+    let steps = config.rounds;
+    let mut report = MatchReport::new(rules.variance());
+    let seed = rng.gen();
+    bot1.new_game(rules, seed)?;
+    bot2.new_game(rules, seed)?;
+    for i in 0..steps {
+        let move1 = timed_move(bot1, rules);
+        let move1 = check_violation(bot1, i, move1, &mut report.violations[0])?;
+        let move2 = timed_move(bot2, rules);
+        let move2 = check_violation(bot2, i, move2, &mut report.violations[1])?;
+        report.forfeited = [
+            move1.is_none() && policy.forfeits_match(report.violations[0]),
+            move2.is_none() && policy.forfeits_match(report.violations[1]),
+        ];
+        if report.forfeited[0] || report.forfeited[1] {
+            // The result stands even if an abort fails. That closes the
+            // connection, and the caller drops the bot.
+            let aborted = [bot1.destroy_game(), bot2.destroy_game()];
+            for (name, res) in [&bot1.name, &bot2.name].iter().zip(aborted.iter()) {
+                if let Err(ref err) = *res {
+                    warn!(target: "battle", "Aborting the game of {} failed: {}", name, err);
+                }
+            }
+            break;
+        }
+        let moved = (move1.is_some(), move2.is_some());
+        let (move1, move2) = match (move1, move2) {
+            (Some(move1), Some(move2)) => (move1, move2),
+            (move1, move2) => match policy {
+                Policy::RandomMove => (
+                    move1.unwrap_or_else(|| rules.random(rng)),
+                    move2.unwrap_or_else(|| rules.random(rng)),
+                ),
+                _ => match (move1, move2) {
+                    (Some(move1), None) => (move1, rules.weakest_against(move1)),
+                    (None, Some(move2)) => (rules.weakest_against(move2), move2),
+                    _ => (Move(0), Move(0)),
+                },
+            },
+        };
+        let sent = (move1, move2);
+        // Noise only changes moves that were sent, not the substitutes.
+        let move1 = if moved.0 { add_noise(config, move1, rng, &mut report.flips[0]) } else { move1 };
+        let move2 = if moved.1 { add_noise(config, move2, rng, &mut report.flips[1]) } else { move2 };
+        let feedback = if config.noisy_feedback { (move1, move2) } else { sent };
+        trace!(target: "battle", "moves are {} and {}", rules.name_of(move1), rules.name_of(move2));
+        report.score[0] += i64::from(rules.score(move1, move2));
+        report.score[1] += i64::from(rules.score(move2, move1));
+        match rules.outcome(move1, move2) {
+            GameOutcome::Win => {
+                report.wins[0] += 1;
+            },
+            GameOutcome::Lose => {
+                report.wins[1] += 1;
+            },
+            GameOutcome::Tie => {
+                report.ties += 1;
+            },
+        }
+        report.rounds += 1;
+        inc(&METRICS.rounds);
+        if i == steps-1 {
+            bot1.end_game(rules, feedback.1)?;
+            bot2.end_game(rules, feedback.0)?;
+        } else {
+            bot1.cont_game(rules, feedback.1)?;
+            bot2.cont_game(rules, feedback.0)?;
+        }
+    }
+    report.duration = now.elapsed();
+    Ok(report)
+}
+
+/// Replaces the move with a random one with the probability given by the
+/// noise of the config, counting it if the move changed.
+fn add_noise<R: Rng>(config: &MatchConfig, m: Move, rng: &mut R, flips: &mut usize) -> Move {
+    if config.noise == 0. || !rng.gen_bool(config.noise) {
+        return m;
+    }
+    let noisy = config.rules.random(rng);
+    if noisy != m {
+        *flips += 1;
+    }
+    noisy
+}
+
+/// Gets a move and records how long it took.
+fn timed_move(bot: &mut Client, rules: &Ruleset) -> IoResult<Move> {
+    let start = Instant::now();
+    let res = bot.get_move(rules);
+    if res.is_ok() {
+        METRICS.move_latency(&bot.name, start.elapsed());
+    }
+    res
+}
+
+/// Turns violations into `None` and counts them, passing other errors on.
+fn check_violation(
+    bot: &Client,
+    round: usize,
+    res: IoResult<Move>,
+    violations: &mut usize,
+) -> IoResult<Option<Move>> {
+    match res {
+        Ok(m) => Ok(Some(m)),
+        Err(ref err) if is_violation(err) => {
+            if is_timeout(err) {
+                METRICS.timeout(&bot.name);
+                warn!(target: "battle", "{} timed out in round {}.", bot.name, round);
+            } else {
+                warn!(target: "battle", "{} made an illegal move in round {}: {}",
+                      bot.name, round, err);
+            }
+            *violations += 1;
+            Ok(None)
+        },
+        Err(err) => Err(err),
+    }
+}
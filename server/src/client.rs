@@ -1,160 +1,328 @@
-use std::net::{TcpStream, SocketAddr};
+use std::collections::HashMap;
 use std::io::{Result as IoResult, Error as IoError, Read, Write, ErrorKind};
 use std::str::from_utf8;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+use auth::Keys;
+use rules::{Move, Ruleset};
+use session::new_token;
+use transport::Transport;
+
+/// How long a client may take to send back the sync token of an aborted game.
+const SYNC_DEADLINE: Duration = Duration::from_secs(10);
+/// How many bytes beyond the token and the owed moves a client may send
+/// before its sync token.
+const SYNC_SLACK: usize = 16;
 
 pub struct Client {
-    pub addr: SocketAddr,
-    stream: TcpStream,
+    /// Identifies the client for as long as it is connected, or longer if it
+    /// reconnects with its session token. Assigned when it is accepted.
+    pub id: usize,
+    /// Where the client connected from, for messages.
+    pub addr: String,
+    stream: Box<dyn Transport>,
+    /// The read and write timeout of the stream.
+    timeout: Option<Duration>,
     pub name: String,
+    /// Options sent in an extended handshake, or `None` for old clients.
+    /// Flags without a value map to the empty string.
+    pub options: Option<HashMap<String, String>>,
+    /// The session token, if the client has been given one.
+    pub token: Option<String>,
+    /// Reference bots are the opponents in a gauntlet.
+    pub reference: bool,
+    /// The team the client authenticated as.
+    pub team: Option<String>,
+    /// Only matches between rated clients change the ratings.
+    pub rated: bool,
+    /// Moves owed from rounds where the client timed out. They are read and
+    /// discarded before the next move or ping, so the stream stays in sync.
+    late_moves: usize,
+    /// Set when the connection was closed because an abort failed.
+    closed: bool,
 }
 impl Client {
-    pub fn new(addr: SocketAddr, mut stream: TcpStream) -> IoResult<Client> {
-        stream.set_read_timeout(Some(Duration::new(10, 0)))?;
-        stream.set_write_timeout(Some(Duration::new(10, 0)))?;
-        let len = {
-            let mut len_buf = [0];
-            stream.read_exact(&mut len_buf)?;
-            usize::from(len_buf[0])
-        };
-        let name = {
-            let mut name_buf = [0; 257];
-            stream.read_exact(&mut name_buf[0..len+1])?;
-            match from_utf8(&name_buf[0..len]) {
-                Ok(s) => String::from(s),
-                Err(_) => return Err(IoError::new(ErrorKind::InvalidData,
-                                                  "name invalid utf8")),
-            }
-        };
+    /// Reads the handshake, and keeps the connection if it is well formed.
+    pub fn new(addr: String, mut stream: Box<dyn Transport>) -> IoResult<Client> {
+        let timeout = Some(Duration::new(10, 0));
+        stream.set_timeout(timeout)?;
+        let (name, options) = read_handshake(&mut stream)?;
         Ok(Client {
+            id: 0,
             addr,
             stream,
+            timeout,
             name,
+            options,
+            token: None,
+            reference: false,
+            team: None,
+            rated: true,
+            late_moves: 0,
+            closed: false,
         })
     }
-    pub fn set_timeout(&mut self, secs: u64) -> IoResult<()> {
-        self.stream.set_read_timeout(Some(Duration::new(secs, 0)))?;
-        self.stream.set_write_timeout(Some(Duration::new(secs, 0)))?;
+    pub fn option(&self, key: &str) -> Option<&str> {
+        match self.options {
+            Some(ref options) => options.get(key).map(|value| value.as_str()),
+            None => None,
+        }
+    }
+    /// Tells an extended client that it was accepted.
+    pub fn accept(&mut self) -> IoResult<()> {
+        if self.options.is_none() {
+            return Ok(());
+        }
+        let reply = match self.token {
+            Some(ref token) => format!("ok token={}", token),
+            None => String::from("ok"),
+        };
+        self.send_line(&reply)
+    }
+    /// Tells an extended client why it was not accepted.
+    pub fn reject(&mut self, reason: &str) -> IoResult<()> {
+        if self.options.is_none() {
+            return Ok(());
+        }
+        self.send_line(&format!("reject {}", reason))
+    }
+    /// Authenticates a client that sent the `team` option. It is sent a line
+    /// with a nonce, and answers with a length prefixed line holding the
+    /// proof described in `Keys`. Other clients are left alone, as are all
+    /// clients when there are no keys.
+    pub fn authenticate(&mut self, keys: &Keys) -> IoResult<()> {
+        let team = match self.option("team") {
+            Some(team) if !keys.is_empty() => String::from(team),
+            _ => return Ok(()),
+        };
+        let nonce = new_token();
+        self.send_line(&format!("nonce={}", nonce))?;
+        let proof = read_line(&mut self.stream)?;
+        if keys.verify(&team, &nonce, &self.name, &proof) {
+            self.team = Some(team);
+            Ok(())
+        } else {
+            let _ = self.reject("bad-auth");
+            Err(IoError::new(ErrorKind::PermissionDenied,
+                format!("{} failed to authenticate as team {}", self.name, team)))
+        }
+    }
+    /// Sends a length prefixed line, which can't be longer than 255 bytes.
+    fn send_line(&mut self, reply: &str) -> IoResult<()> {
+        if reply.len() > 255 {
+            return Err(IoError::new(ErrorKind::InvalidInput,
+                format!("the line {:?} is longer than 255 bytes", reply)));
+        }
+        let mut buf = vec![reply.len() as u8];
+        buf.extend_from_slice(reply.as_bytes());
+        self.stream.write_all(&buf)?;
+        self.stream.flush()?;
         Ok(())
     }
+    pub fn set_timeout(&mut self, secs: u64) -> IoResult<()> {
+        self.timeout = Some(Duration::new(secs, 0));
+        self.stream.set_timeout(self.timeout)
+    }
     pub fn remove_timeout(&mut self) -> IoResult<()> {
-        self.stream.set_read_timeout(None)?;
-        self.stream.set_write_timeout(None)?;
-        Ok(())
+        self.timeout = None;
+        self.stream.set_timeout(None)
     }
     pub fn shutdown(mut self) {
-        let _ = self.stream.write(&['x' as u8]);
+        let _ = self.stream.write_all(b"x");
         let _ = self.stream.flush();
+        let _ = self.stream.shutdown();
+    }
+    /// Returns true if the client can play a game with these rules.
+    pub fn supports(&self, rules: &Ruleset) -> bool {
+        rules.is_classic() || self.option("rules").is_some()
     }
-    pub fn new_game(&mut self) -> IoResult<()> {
-        self.stream.write(&['n' as u8])?;
+    /// Starts a game. Clients with the `rules` or `seed` options are sent a
+    /// line after the `n`, with a description of the ruleset and the seed of
+    /// the game respectively.
+    pub fn new_game(&mut self, rules: &Ruleset, seed: u64) -> IoResult<()> {
+        self.stream.write_all(b"n")?;
+        let mut info = Vec::new();
+        if self.option("rules").is_some() {
+            info.push(rules.describe());
+        }
+        if self.option("seed").is_some() {
+            info.push(format!("seed={}", seed));
+        }
+        if !info.is_empty() {
+            self.send_line(&info.join(" "))?;
+        }
         self.stream.flush()?;
         Ok(())
     }
-    pub fn cont_game(&mut self, m: Move) -> IoResult<()> {
-        self.stream.write(&[m.into_u8()])?;
+    pub fn cont_game(&mut self, rules: &Ruleset, m: Move) -> IoResult<()> {
+        self.stream.write_all(&[rules.symbol(m)])?;
         self.stream.flush()?;
         Ok(())
     }
-    pub fn end_game(&mut self, m: Move) -> IoResult<()> {
-        self.stream.write(&[m.into_u8_end()])?;
+    pub fn end_game(&mut self, rules: &Ruleset, m: Move) -> IoResult<()> {
+        self.stream.write_all(&[rules.symbol(m) ^ b' '])?;
         self.stream.flush()?;
         Ok(())
     }
+    /// Aborts the game. Clients with the `abort` option are sent a sync
+    /// token after the `e`, and the stream is read until they send it back,
+    /// so no late move is left to be read in the next game. For other clients
+    /// whatever has arrived is discarded, which misses moves still on the way.
+    /// A client that doesn't send the token back within `SYNC_DEADLINE`, or
+    /// sends too much else first, gets an error and its connection is closed,
+    /// as the stream can't be brought back in sync.
     pub fn destroy_game(&mut self) -> IoResult<()> {
-        self.stream.write(&['e' as u8])?;
-        self.stream.flush()?;
-        self.stream.set_nonblocking(true)?;
-        let mut buf = [0; 1024];
-        loop {
-            match self.stream.read(&mut buf) {
-                Ok(0) => break,
-                Ok(_) => continue,
-                Err(_) => break,
+        self.stream.write_all(b"e")?;
+        if self.option("abort").is_none() {
+            self.stream.flush()?;
+            self.stream.drain()?;
+            self.late_moves = 0;
+            return Ok(());
+        }
+        let token = new_token();
+        self.send_line(&token)?;
+        let res = self.await_sync(&token);
+        if res.is_err() {
+            self.closed = true;
+            let _ = self.stream.shutdown();
+            return res;
+        }
+        self.stream.set_timeout(self.timeout)?;
+        self.late_moves = 0;
+        Ok(())
+    }
+    /// Returns true if the connection was closed after a failed abort.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+    /// Reads until the length prefixed token arrives.
+    fn await_sync(&mut self, token: &str) -> IoResult<()> {
+        let mut expected = vec![token.len() as u8];
+        expected.extend_from_slice(token.as_bytes());
+        let mut budget = expected.len() + self.late_moves + SYNC_SLACK;
+        let deadline = Instant::now() + SYNC_DEADLINE;
+        let mut matched = 0;
+        while matched < expected.len() {
+            let now = Instant::now();
+            if budget == 0 || now >= deadline {
+                return Err(IoError::new(ErrorKind::InvalidData,
+                    "the sync token of the aborted game never came back"));
+            }
+            budget -= 1;
+            let remaining = deadline - now;
+            self.stream.set_timeout(Some(self.timeout.map_or(remaining, |t| t.min(remaining))))?;
+            let byte = self.read_byte()?;
+            if byte == expected[matched] {
+                matched += 1;
+            } else if byte == expected[0] {
+                matched = 1;
+            } else {
+                matched = 0;
             }
         }
-        self.stream.set_nonblocking(false)?;
         Ok(())
     }
-    pub fn get_move(&mut self) -> IoResult<Move> {
+    /// Reads a move. If this times out, the move is remembered as owed and
+    /// skipped once it arrives.
+    pub fn get_move(&mut self, rules: &Ruleset) -> IoResult<Move> {
+        let res = self.skip_late_moves().and_then(|()| self.read_byte());
+        match res {
+            Ok(byte) => rules.parse(byte),
+            Err(err) => {
+                if is_timeout(&err) {
+                    self.late_moves += 1;
+                }
+                Err(err)
+            },
+        }
+    }
+    fn skip_late_moves(&mut self) -> IoResult<()> {
+        while self.late_moves > 0 {
+            self.read_byte()?;
+            self.late_moves -= 1;
+        }
+        Ok(())
+    }
+    fn read_byte(&mut self) -> IoResult<u8> {
         let mut buf = [0];
         self.stream.read_exact(&mut buf)?;
-        Move::try_from(buf[0])
+        Ok(buf[0])
     }
     pub fn ping(&mut self) -> IoResult<()> {
-        self.stream.write(&[' ' as u8])?;
+        self.skip_late_moves()?;
+        self.stream.write_all(b" ")?;
         self.stream.flush()?;
-        let mut buf = [0];
-        self.stream.read_exact(&mut buf)?;
-        if buf[0] == (' ' as u8) {
+        let byte = self.read_byte()?;
+        if byte == b' ' {
             Ok(())
         } else {
             Err(IoError::new(ErrorKind::InvalidData,
-                format!("invalid ping response got {} expected space.", buf[0] as u8)))
+                format!("invalid ping response got {} expected space.", byte)))
         }
     }
 }
 
-#[derive(Clone,Copy,Debug)]
-pub enum Move {
-    Rock, Paper, Scissor,
+/// Reads a handshake, returning the name and the options. It consists of a
+/// length byte, the name and a newline. Extended clients send a `?` instead
+/// of the newline, followed by a length byte and a space separated list of
+/// `key=value` options. These clients are answered with a length prefixed
+/// reply once they are accepted or rejected by the server.
+pub fn read_handshake<R: Read>(
+    stream: &mut R,
+) -> IoResult<(String, Option<HashMap<String, String>>)> {
+    let len = {
+        let mut len_buf = [0];
+        stream.read_exact(&mut len_buf)?;
+        usize::from(len_buf[0])
+    };
+    let mut name_buf = vec![0; len + 1];
+    stream.read_exact(&mut name_buf)?;
+    let name = match from_utf8(&name_buf[..len]) {
+        Ok(s) => String::from(s),
+        Err(_) => return Err(IoError::new(ErrorKind::InvalidData,
+                                          "name invalid utf8")),
+    };
+    let options = match name_buf[len] {
+        b'?' => Some(read_options(stream)?),
+        _ => None,
+    };
+    Ok((name, options))
 }
-#[derive(Clone,Copy,Debug)]
-pub enum GameOutcome {
-    Win, Lose, Tie,
+
+/// Reads a length prefixed line.
+fn read_line<R: Read>(stream: &mut R) -> IoResult<String> {
+    let mut len_buf = [0];
+    stream.read_exact(&mut len_buf)?;
+    let mut buf = vec![0; usize::from(len_buf[0])];
+    stream.read_exact(&mut buf)?;
+    match String::from_utf8(buf) {
+        Ok(line) => Ok(line),
+        Err(_) => Err(IoError::new(ErrorKind::InvalidData, "line invalid utf8")),
+    }
 }
-impl Move {
-    pub fn try_from(byte: u8) -> IoResult<Move> {
-        Ok(match byte as char {
-            'r' => {
-                Move::Rock
-            },
-            'p' => {
-                Move::Paper
-            },
-            's' => {
-                Move::Scissor
-            },
-            _ => {
-                return Err(IoError::new(ErrorKind::InvalidData,
-                    format!("byte {} is not r, p or s", byte)));
-            }
+
+/// Reads the length prefixed option list of an extended handshake.
+fn read_options<R: Read>(stream: &mut R) -> IoResult<HashMap<String, String>> {
+    let options = read_line(stream)?;
+    Ok(options.split_whitespace()
+        .map(|option| match option.find('=') {
+            Some(i) => (String::from(&option[..i]), String::from(&option[i+1..])),
+            None => (String::from(option), String::new()),
         })
-    }
-    /// Returns win if self wins.
-    pub fn game_outcome(self, other: Move) -> GameOutcome {
-        match self {
-            Move::Rock => {
-                match other {
-                    Move::Rock => GameOutcome::Tie,
-                    Move::Paper => GameOutcome::Lose,
-                    Move::Scissor => GameOutcome::Win,
-                }
-            },
-            Move::Paper => {
-                match other {
-                    Move::Rock => GameOutcome::Win,
-                    Move::Paper => GameOutcome::Tie,
-                    Move::Scissor => GameOutcome::Lose,
-                }
-            },
-            Move::Scissor => {
-                match other {
-                    Move::Rock => GameOutcome::Lose,
-                    Move::Paper => GameOutcome::Win,
-                    Move::Scissor => GameOutcome::Tie,
-                }
-            },
-        }
-    }
-    pub fn into_u8(self) -> u8 {
-        (match self {
-            Move::Rock => 'r',
-            Move::Paper => 'p',
-            Move::Scissor => 's',
-        }) as u8
-    }
-    pub fn into_u8_end(self) -> u8 {
-        self.into_u8() ^ (' ' as u8)
-    }
+        .collect())
+}
+
+/// Returns true if the name isn't empty and has no control characters.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.chars().any(char::is_control)
+}
+
+/// Returns true if the error is caused by the read or write timeout.
+pub fn is_timeout(err: &IoError) -> bool {
+    matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+/// Returns true if the error is the client breaking the rules of a game,
+/// rather than the connection failing.
+pub fn is_violation(err: &IoError) -> bool {
+    is_timeout(err) || err.kind() == ErrorKind::InvalidData
 }
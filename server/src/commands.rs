@@ -0,0 +1,817 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::process;
+use std::sync::{Arc, Mutex};
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::Helper;
+use rustyline::Result as RlResult;
+
+use log::LevelFilter;
+
+use battle::{Policy, MatchConfig};
+use logging::{self, LEVELS, TARGETS};
+use rules::Ruleset;
+use gauntlet::gauntlet;
+use tournament::{Format, tournament};
+use State;
+
+/// The kind of value an argument accepts.
+#[derive(Clone,Copy)]
+pub enum Kind {
+    /// A connected bot, given by name or id.
+    Bot,
+    /// A non-negative integer.
+    Number,
+    /// One of a fixed set of words.
+    Word(&'static [&'static str]),
+    /// The name of a command.
+    Command,
+    /// A path to a file.
+    File,
+    /// Any word.
+    Text,
+    /// The rest of the line, as `key=value` pairs with the given keys.
+    Options(&'static [&'static str]),
+}
+
+pub struct Arg {
+    pub name: &'static str,
+    pub kind: Kind,
+    pub optional: bool,
+}
+const fn arg(name: &'static str, kind: Kind) -> Arg {
+    Arg { name, kind, optional: false }
+}
+const fn opt(name: &'static str, kind: Kind) -> Arg {
+    Arg { name, kind, optional: true }
+}
+
+pub struct Command {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub args: &'static [Arg],
+    pub help: &'static str,
+    pub run: fn(&mut State, &Args) -> Control,
+}
+
+/// What happens after a command has run.
+pub enum Control {
+    Continue,
+    /// Stop the server.
+    Exit,
+    /// Run the commands in the file.
+    Source(String),
+}
+impl Command {
+    pub fn usage(&self) -> String {
+        let mut usage = String::from(self.name);
+        for arg in self.args {
+            usage.push(' ');
+            usage.push_str(&arg.usage());
+        }
+        usage
+    }
+    fn matches(&self, name: &str) -> bool {
+        self.name == name || self.aliases.contains(&name)
+    }
+}
+impl Arg {
+    fn usage(&self) -> String {
+        let inner = match self.kind {
+            Kind::Word(words) => words.join("|"),
+            Kind::Options(keys) => {
+                let keys: Vec<String> = keys.iter().map(|key| format!("{}=", key)).collect();
+                keys.join(" ")
+            },
+            Kind::Bot | Kind::Number | Kind::Command | Kind::File | Kind::Text => {
+                String::from(self.name)
+            },
+        };
+        if self.optional {
+            format!("[{}]", inner)
+        } else {
+            format!("<{}>", inner)
+        }
+    }
+}
+
+/// The parsed arguments of a command. Missing optional arguments are `None`.
+pub struct Args<'a> {
+    values: Vec<Option<Value<'a>>>,
+}
+enum Value<'a> {
+    Text(&'a str),
+    Number(u64),
+    Options(Vec<(&'a str, &'a str)>),
+}
+impl<'a> Args<'a> {
+    /// The bot or word at position `i`.
+    pub fn text(&self, i: usize) -> Option<&'a str> {
+        match self.values.get(i) {
+            Some(&Some(Value::Text(text))) => Some(text),
+            _ => None,
+        }
+    }
+    pub fn number(&self, i: usize) -> Option<u64> {
+        match self.values.get(i) {
+            Some(&Some(Value::Number(n))) => Some(n),
+            _ => None,
+        }
+    }
+    pub fn options(&self, i: usize) -> &[(&'a str, &'a str)] {
+        match self.values.get(i) {
+            Some(&Some(Value::Options(ref options))) => options,
+            _ => &[],
+        }
+    }
+}
+
+pub static COMMANDS: &[Command] = &[
+    Command {
+        name: "help",
+        aliases: &[],
+        args: &[opt("command", Kind::Command)],
+        help: "List the commands, or describe one of them.",
+        run: help,
+    },
+    Command {
+        name: "exit",
+        aliases: &["quit"],
+        args: &[],
+        help: "Disconnect every bot and stop the server.",
+        run: |_, _| Control::Exit,
+    },
+    Command {
+        name: "source",
+        aliases: &[],
+        args: &[arg("file", Kind::File)],
+        help: "Run the commands in a file, one per line. Lines starting with # are skipped. \
+               The file .rpsrc is sourced when the server starts.",
+        run: |_, args| Control::Source(String::from(args.text(0).unwrap())),
+    },
+    Command {
+        name: "wait",
+        aliases: &[],
+        args: &[arg("bots", Kind::Number), opt("secs", Kind::Number)],
+        help: "Wait until the given number of bots are connected.",
+        run: |state, args| {
+            state.wait_for_clients(args.number(0).unwrap() as usize, args.number(1));
+            Control::Continue
+        },
+    },
+    Command {
+        name: "ping",
+        aliases: &[],
+        args: &[],
+        help: "Ping every bot, remove the dead ones and list the rest.",
+        run: |state, _| {
+            state.print_messages();
+            state.ping();
+            Control::Continue
+        },
+    },
+    Command {
+        name: "listen",
+        aliases: &[],
+        args: &[arg("path", Kind::File)],
+        help: "Accept bots on a Unix socket at the path as well.",
+        run: listen,
+    },
+    Command {
+        name: "spawn",
+        aliases: &[],
+        args: &[arg("program", Kind::File), opt("arg", Kind::Text)],
+        help: "Start a bot as a child process, which plays over its stdin and stdout.",
+        run: |state, args| {
+            let mut command = process::Command::new(args.text(0).unwrap());
+            command.args(args.text(1));
+            if let Err(err) = state.spawn(&mut command) {
+                println!("Unable to start {}: {}", args.text(0).unwrap(), err);
+            }
+            Control::Continue
+        },
+    },
+    Command {
+        name: "kick",
+        aliases: &[],
+        args: &[arg("bot", Kind::Bot)],
+        help: "Disconnect a bot. It may connect again.",
+        run: |state, args| {
+            state.kick(args.text(0).unwrap());
+            Control::Continue
+        },
+    },
+    Command {
+        name: "ban",
+        aliases: &[],
+        args: &[opt("name|ip", Kind::Text)],
+        help: "List the bans, or ban a name or an IP address and disconnect the bots it \
+               matches. Banned addresses are refused before the handshake. The list is \
+               kept in .rps_bans.",
+        run: |state, args| {
+            match args.text(0) {
+                Some(entry) => state.ban(entry),
+                None => {
+                    let bans = state.bans();
+                    if bans.is_empty() {
+                        println!("Nobody is banned.");
+                    }
+                    for entry in bans {
+                        println!("{} is banned.", entry);
+                    }
+                },
+            }
+            Control::Continue
+        },
+    },
+    Command {
+        name: "unban",
+        aliases: &[],
+        args: &[arg("name|ip", Kind::Text)],
+        help: "Lift the ban on a name or an IP address.",
+        run: |state, args| {
+            state.unban(args.text(0).unwrap());
+            Control::Continue
+        },
+    },
+    Command {
+        name: "rename",
+        aliases: &[],
+        args: &[arg("bot", Kind::Bot), arg("name", Kind::Text)],
+        help: "Give a bot a new name, which it keeps if it reconnects with its session token.",
+        run: |state, args| {
+            state.rename(args.text(0).unwrap(), args.text(1).unwrap());
+            Control::Continue
+        },
+    },
+    Command {
+        name: "limits",
+        aliases: &[],
+        args: &[opt("options", Kind::Options(&["clients", "per-ip", "rate"]))],
+        help: "Show or set the most clients that may be connected, the most from one \
+               address, and how many connections an address may make per second. \
+               Addresses that connect faster are refused for a while, which doubles \
+               each time. A limit is removed with off.",
+        run: limits,
+    },
+    Command {
+        name: "timeout",
+        aliases: &[],
+        args: &[arg("secs", Kind::Number)],
+        help: "Set how long bots may take to respond.",
+        run: |state, args| {
+            let timeout = args.number(0).unwrap();
+            state.set_timeout(Some(timeout));
+            println!("Setting timeout to {} secs.", timeout);
+            Control::Continue
+        },
+    },
+    Command {
+        name: "notimeout",
+        aliases: &[],
+        args: &[],
+        help: "Let bots take as long as they want to respond.",
+        run: |state, _| {
+            state.set_timeout(None);
+            println!("Removing timeout.");
+            Control::Continue
+        },
+    },
+    Command {
+        name: "policy",
+        aliases: &[],
+        args: &[
+            arg("policy", Kind::Word(&["round", "match", "random", "dq"])),
+            opt("violations", Kind::Number),
+        ],
+        help: "Choose what happens when a bot times out or makes an illegal move. \
+               With dq the bot is disqualified after the given number of violations.",
+        run: policy,
+    },
+    Command {
+        name: "battle",
+        aliases: &[],
+        args: &[
+            arg("bot", Kind::Bot),
+            arg("bot", Kind::Bot),
+            arg("rounds", Kind::Number),
+            opt("options", Kind::Options(MATCH_OPTIONS)),
+        ],
+        help: "Play a game between two bots. The ruleset is chosen with rules=<name>, \
+               and games=<n> plays n games of the given number of rounds. \
+               With noise=<p> each move is replaced by a random one with probability p, \
+               and feedback=true tells the opponent the move that was sent instead. \
+               The match is played with seed=<n>, or a new seed that is printed.",
+        run: |state, args| {
+            if let Some(config) = match_config(state, args.number(2).unwrap(), args.options(3)) {
+                state.long_battle(args.text(0).unwrap(), args.text(1).unwrap(), &config);
+            }
+            Control::Continue
+        },
+    },
+    Command {
+        name: "tournament",
+        aliases: &[],
+        args: &[
+            arg("format", Kind::Word(&["swiss", "single", "double"])),
+            arg("rounds", Kind::Number),
+            opt("options", Kind::Options(TOURNAMENT_OPTIONS)),
+        ],
+        help: "Play a tournament between all connected bots, seeded by rating. Swiss runs \
+               stages=<n> rounds, by default enough to find a single winner. Single and \
+               double elimination give ties to the higher seed. Takes the options of battle.",
+        run: run_tournament,
+    },
+    Command {
+        name: "gauntlet",
+        aliases: &[],
+        args: &[
+            arg("bot", Kind::Bot),
+            arg("rounds", Kind::Number),
+            opt("options", Kind::Options(MATCH_OPTIONS)),
+        ],
+        help: "Play a bot against every reference bot and print a scorecard with the win \
+               rate, its 95% confidence interval and a verdict for each opponent. \
+               Takes the options of battle.",
+        run: |state, args| {
+            if let Some(config) = match_config(state, args.number(1).unwrap(), args.options(2)) {
+                gauntlet(state, args.text(0).unwrap(), &config);
+            }
+            Control::Continue
+        },
+    },
+    Command {
+        name: "reference",
+        aliases: &[],
+        args: &[opt("bot", Kind::Bot), opt("flag", Kind::Word(&["yes", "no"]))],
+        help: "List the reference bots, or mark a bot as a reference bot or not. Bots can \
+               also mark themselves with the reference option in the handshake.",
+        run: reference,
+    },
+    Command {
+        name: "ladder",
+        aliases: &[],
+        args: &[
+            opt("action", Kind::Word(&["pause", "resume", "status"])),
+            opt("rounds", Kind::Number),
+            opt("options", Kind::Options(MATCH_OPTIONS)),
+        ],
+        help: "Pause or resume the ladder, which keeps pairing idle bots of similar rating \
+               in the background. Resuming with a number of rounds and the options of \
+               battle changes the matches it plays, and resuming always picks up the \
+               current policy.",
+        run: ladder,
+    },
+    Command {
+        name: "ratings",
+        aliases: &[],
+        args: &[],
+        help: "List the ratings of the connected bots.",
+        run: ratings,
+    },
+    Command {
+        name: "loglevel",
+        aliases: &[],
+        args: &[opt("level", Kind::Word(LEVELS)), opt("target", Kind::Word(TARGETS))],
+        help: "Show the log levels, or set the level of one target or of all of them. \
+               The moves of each round are logged at the trace level.",
+        run: loglevel,
+    },
+    Command {
+        name: "rules",
+        aliases: &[],
+        args: &[opt("name", Kind::Text), opt("moves", Kind::Text), opt("payoff", Kind::Text)],
+        help: "List the rulesets, or define a new one. The moves are up to 8 lowercase letters, \
+               and the payoff is a comma separated list with the points move a scores \
+               against move b at position a*moves+b.",
+        run: rules,
+    },
+];
+
+#[cfg(unix)]
+fn listen(state: &mut State, args: &Args) -> Control {
+    let path = args.text(0).unwrap();
+    state.listen_unix(PathBuf::from(path));
+    println!("Listening on {}.", path);
+    Control::Continue
+}
+#[cfg(not(unix))]
+fn listen(_state: &mut State, _args: &Args) -> Control {
+    println!("Unix sockets aren't supported on this platform.");
+    Control::Continue
+}
+
+fn help(_state: &mut State, args: &Args) -> Control {
+    match args.text(0) {
+        Some(name) => match find_command(name) {
+            Some(command) => print_help(command),
+            None => println!("Unknown command {}.", name),
+        },
+        None => {
+            for command in COMMANDS {
+                print_help(command);
+            }
+        },
+    }
+    Control::Continue
+}
+
+fn print_help(command: &Command) {
+    println!("{}", command.usage());
+    println!("    {}", command.help);
+}
+
+fn policy(state: &mut State, args: &Args) -> Control {
+    let policy = match args.text(0).unwrap() {
+        "round" => Policy::ForfeitRound,
+        "match" => Policy::ForfeitMatch,
+        "random" => Policy::RandomMove,
+        _ => match args.number(1) {
+            Some(limit) if limit > 0 => Policy::Disqualify(limit as usize),
+            _ => {
+                println!("dq requires a positive number of violations.");
+                return Control::Continue;
+            }
+        },
+    };
+    state.policy = policy;
+    println!("On violations bots will {}.", policy);
+    Control::Continue
+}
+
+/// The options accepted by commands that play matches.
+const MATCH_OPTIONS: &[&str] = &["rules", "games", "noise", "feedback", "seed"];
+
+const TOURNAMENT_OPTIONS: &[&str] = &["stages", "rules", "games", "noise", "feedback", "seed"];
+
+fn match_config(state: &State, rounds: u64, options: &[(&str, &str)]) -> Option<MatchConfig> {
+    let mut config = MatchConfig::new(rounds as usize, state.policy);
+    let rounds = rounds.to_string();
+    let options = Some(("rounds", rounds.as_str())).into_iter()
+        .chain(options.iter().cloned().filter(|&(key, _)| MATCH_OPTIONS.contains(&key)));
+    for (key, value) in options {
+        if let Err(msg) = config.set_option(key, value, &state.rulesets) {
+            println!("{}", msg);
+            return None;
+        }
+    }
+    Some(config)
+}
+
+fn run_tournament(state: &mut State, args: &Args) -> Control {
+    let config = match match_config(state, args.number(1).unwrap(), args.options(2)) {
+        Some(config) => config,
+        None => return Control::Continue,
+    };
+    let format = match args.text(0).unwrap() {
+        "swiss" => {
+            let stages = args.options(2).iter().find(|&&(key, _)| key == "stages");
+            let stages = match stages {
+                Some(&(_, value)) => match value.parse() {
+                    Ok(stages) => stages,
+                    Err(_) => {
+                        println!("{} is not a number of stages.", value);
+                        return Control::Continue;
+                    },
+                },
+                None => {
+                    let entrants = state.clients.iter()
+                        .filter(|client| client.supports(&config.rules))
+                        .count();
+                    entrants.max(2).next_power_of_two().trailing_zeros() as usize
+                },
+            };
+            Format::Swiss(stages)
+        },
+        "single" => Format::SingleElimination,
+        _ => Format::DoubleElimination,
+    };
+    tournament(state, format, &config);
+    Control::Continue
+}
+
+fn reference(state: &mut State, args: &Args) -> Control {
+    let bot = match args.text(0) {
+        Some(bot) => bot,
+        None => {
+            for client in state.clients.iter().filter(|client| client.reference) {
+                println!("{}", client.name);
+            }
+            return Control::Continue;
+        },
+    };
+    match state.find(bot) {
+        Some(i) => {
+            let client = &mut state.clients[i];
+            client.reference = args.text(1) != Some("no");
+            let what = if client.reference { "a" } else { "not a" };
+            println!("{} is {} reference bot.", client.name, what);
+        },
+        None => println!("no such bot {}", bot),
+    }
+    Control::Continue
+}
+
+fn ladder(state: &mut State, args: &Args) -> Control {
+    match args.text(0) {
+        Some("pause") => {
+            state.ladder.running = false;
+            println!("The ladder is paused.");
+        },
+        Some("resume") => {
+            if let Some(rounds) = args.number(1) {
+                match match_config(state, rounds, args.options(2)) {
+                    Some(config) => state.ladder.config = config,
+                    None => return Control::Continue,
+                }
+            }
+            state.ladder.config.policy = state.policy;
+            state.ladder.running = true;
+            println!("The ladder is running.");
+        },
+        _ => {
+            let config = &state.ladder.config;
+            println!("The ladder is {}, playing {} games of {} rounds of {}, and on violations bots {}.",
+                     if state.ladder.running { "running" } else { "paused" },
+                     config.games, config.rounds, config.rules.name, config.policy);
+        },
+    }
+    Control::Continue
+}
+
+fn limits(state: &mut State, args: &Args) -> Control {
+    let mut limits = state.limits.lock().unwrap();
+    for &(key, value) in args.options(0) {
+        let valid = match (key, value) {
+            ("clients", "off") => { limits.max_clients = None; true },
+            ("per-ip", "off") => { limits.max_per_ip = None; true },
+            (_, "off") => { limits.accept_rate = None; true },
+            ("clients", n) => n.parse().map(|n| limits.max_clients = Some(n)).is_ok(),
+            ("per-ip", n) => n.parse().map(|n| limits.max_per_ip = Some(n)).is_ok(),
+            (_, rate) => match rate.parse::<f64>() {
+                Ok(rate) if rate > 0. => { limits.accept_rate = Some(rate); true },
+                _ => false,
+            },
+        };
+        if !valid {
+            println!("{} is not a valid value for {}.", value, key);
+            return Control::Continue;
+        }
+    }
+    let show = |limit: Option<String>| limit.unwrap_or_else(|| String::from("off"));
+    println!("clients={} per-ip={} rate={}",
+             show(limits.max_clients.map(|n| n.to_string())),
+             show(limits.max_per_ip.map(|n| n.to_string())),
+             show(limits.accept_rate.map(|rate| rate.to_string())));
+    Control::Continue
+}
+
+fn ratings(state: &mut State, _args: &Args) -> Control {
+    let mut clients: Vec<_> = state.clients.iter()
+        .map(|client| (client.name.as_str(), state.ratings.get(client.id), client.rated))
+        .collect();
+    clients.sort_by(|a, b| b.1.elo.partial_cmp(&a.1.elo).unwrap());
+    for (name, rating, rated) in clients {
+        let practice = if rated { "" } else { " (practice)" };
+        println!("{:<20} {:>6.0} after {} matches{}", name, rating.elo, rating.matches, practice);
+    }
+    Control::Continue
+}
+
+fn loglevel(_state: &mut State, args: &Args) -> Control {
+    let level = match args.text(0) {
+        Some(level) => level.parse::<LevelFilter>().unwrap(),
+        None => {
+            for (target, level) in logging::levels() {
+                println!("{:<10} {}", target, level);
+            }
+            return Control::Continue;
+        },
+    };
+    logging::set_level(args.text(1), level);
+    Control::Continue
+}
+
+fn rules(state: &mut State, args: &Args) -> Control {
+    match (args.text(0), args.text(1), args.text(2)) {
+        (None, _, _) => {
+            for rules in &state.rulesets {
+                println!("{}", rules.describe());
+            }
+        },
+        (Some(name), Some(moves), Some(payoff)) => {
+            match Ruleset::new(name, moves, payoff) {
+                Ok(rules) => {
+                    println!("Defined {}.", rules.describe());
+                    state.rulesets.retain(|old| old.name != name);
+                    state.rulesets.push(rules);
+                },
+                Err(msg) => println!("{}", msg),
+            }
+        },
+        _ => println!("A ruleset needs a name, moves and a payoff matrix."),
+    }
+    Control::Continue
+}
+
+fn find_command(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|command| command.matches(name))
+}
+
+/// Parses a line of input. Empty lines give `Ok(None)`.
+pub fn parse(line: &str) -> Result<Option<(&'static Command, Args<'_>)>, String> {
+    let mut words = line.split_whitespace();
+    let name = match words.next() {
+        Some(name) => name,
+        None => return Ok(None),
+    };
+    let command = match find_command(name) {
+        Some(command) => command,
+        None => return Err(format!("Unknown command {}. Type help for a list of commands.", name)),
+    };
+    let mut values = Vec::new();
+    for arg in command.args {
+        if let Kind::Options(keys) = arg.kind {
+            let mut options = Vec::new();
+            for word in words.by_ref() {
+                let i = match word.find('=') {
+                    Some(i) => i,
+                    None => return Err(format!("{} is not of the form key=value.", word)),
+                };
+                if !keys.contains(&&word[..i]) {
+                    return Err(format!("Unknown option {}. Usage: {}", &word[..i], command.usage()));
+                }
+                options.push((&word[..i], &word[i+1..]));
+            }
+            values.push(Some(Value::Options(options)));
+            continue;
+        }
+        let word = match words.next() {
+            Some(word) => word,
+            None if arg.optional => {
+                values.push(None);
+                continue;
+            },
+            None => return Err(format!("Missing argument {}. Usage: {}", arg.name, command.usage())),
+        };
+        let value = match arg.kind {
+            Kind::Bot | Kind::Command | Kind::File | Kind::Text => Value::Text(word),
+            Kind::Options(_) => unreachable!(),
+            Kind::Number => match word.parse() {
+                Ok(n) => Value::Number(n),
+                Err(_) => return Err(format!("{} is not a number.", word)),
+            },
+            Kind::Word(choices) => {
+                if !choices.contains(&word) {
+                    return Err(format!("{} must be one of {}.", arg.name, choices.join(", ")));
+                }
+                Value::Text(word)
+            },
+        };
+        values.push(Some(value));
+    }
+    if words.next().is_some() {
+        return Err(format!("Too many arguments. Usage: {}", command.usage()));
+    }
+    Ok(Some((command, Args { values })))
+}
+
+/// How deeply `source` commands may be nested.
+const MAX_SOURCE_DEPTH: usize = 8;
+
+/// Runs a line of input. Returns false if the server should exit.
+pub fn execute(state: &mut State, line: &str) -> bool {
+    execute_nested(state, line, 0)
+}
+
+fn execute_nested(state: &mut State, line: &str, depth: usize) -> bool {
+    match parse(line) {
+        Ok(Some((command, args))) => match (command.run)(state, &args) {
+            Control::Continue => {},
+            Control::Exit => return false,
+            Control::Source(path) => return source(state, &path, depth),
+        },
+        Ok(None) => {},
+        Err(msg) => println!("{}", msg),
+    }
+    true
+}
+
+/// Runs the commands in a file. Returns false if one of them was exit.
+fn source(state: &mut State, path: &str, depth: usize) -> bool {
+    if depth >= MAX_SOURCE_DEPTH {
+        println!("Not sourcing {}: too deeply nested.", path);
+        return true;
+    }
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) => {
+            println!("Unable to open {}: {}", path, err);
+            return true;
+        },
+    };
+    for line in BufReader::new(file).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => {
+                println!("Error while reading {}: {}", path, err);
+                break;
+            },
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        println!(">> {}", line);
+        if !execute_nested(state, line, depth + 1) {
+            return false;
+        }
+    }
+    true
+}
+
+/// Completes and hints command names, arguments and bot names at the prompt.
+pub struct ReplHelper {
+    state: Arc<Mutex<State>>,
+}
+impl ReplHelper {
+    pub fn new(state: Arc<Mutex<State>>) -> ReplHelper {
+        ReplHelper { state }
+    }
+    /// The words that can be written at the argument position `i` of the
+    /// command, or the command names if there is no command.
+    fn candidates(&self, command: Option<&str>, i: usize) -> Vec<String> {
+        let command = match command {
+            Some(name) => match find_command(name) {
+                Some(command) => command,
+                None => return Vec::new(),
+            },
+            None => return COMMANDS.iter().map(|command| String::from(command.name)).collect(),
+        };
+        let options = command.args.last()
+            .filter(|arg| matches!(arg.kind, Kind::Options(_)));
+        match command.args.get(i).or(options).map(|arg| arg.kind) {
+            Some(Kind::Word(words)) => words.iter().map(|&word| String::from(word)).collect(),
+            Some(Kind::Bot) => match self.state.try_lock() {
+                Ok(state) => state.clients.iter().map(|client| client.name.clone()).collect(),
+                Err(_) => Vec::new(),
+            },
+            Some(Kind::Command) => self.candidates(None, 0),
+            Some(Kind::Options(keys)) => keys.iter().map(|key| format!("{}=", key)).collect(),
+            Some(Kind::Number) | Some(Kind::File) | Some(Kind::Text) | None => Vec::new(),
+        }
+    }
+}
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize) -> RlResult<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map(|i| i + 1).unwrap_or(0);
+        let mut words = line[..start].split_whitespace();
+        let command = words.next();
+        let candidates = self.candidates(command, words.count())
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(&line[start..]))
+            .collect();
+        Ok((start, candidates))
+    }
+}
+impl Hinter for ReplHelper {
+    fn hint(&self, line: &str, pos: usize) -> Option<String> {
+        if pos < line.len() {
+            return None;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let ends_in_space = line.ends_with(char::is_whitespace);
+        if words.len() == 1 && !ends_in_space && find_command(words[0]).is_none() {
+            let mut matches = COMMANDS.iter()
+                .filter(|command| command.name.starts_with(words[0]));
+            return match (matches.next(), matches.next()) {
+                (Some(command), None) => Some(String::from(&command.name[words[0].len()..])),
+                _ => None,
+            };
+        }
+        let command = find_command(words.first()?)?;
+        let rest: Vec<String> = command.args.iter()
+            .skip(words.len() - 1)
+            .map(Arg::usage)
+            .collect();
+        if rest.is_empty() {
+            return None;
+        }
+        let sep = if ends_in_space { "" } else { " " };
+        Some(format!("{}{}", sep, rest.join(" ")))
+    }
+}
+impl Highlighter for ReplHelper {
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(format!("\x1b[2m{}\x1b[0m", hint))
+    }
+}
+impl Helper for ReplHelper {}
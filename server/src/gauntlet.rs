@@ -0,0 +1,96 @@
+use battle::MatchConfig;
+use State;
+
+/// The z-score of a 95% confidence interval.
+const Z: f64 = 1.96;
+
+/// The Wilson score interval of a win rate, given the wins and the number of
+/// rounds that weren't tied.
+fn wilson(wins: usize, decided: usize) -> (f64, f64) {
+    if decided == 0 {
+        return (0., 1.);
+    }
+    let n = decided as f64;
+    let p = wins as f64 / n;
+    let center = (p + Z * Z / (2. * n)) / (1. + Z * Z / n);
+    let half = Z * (p * (1. - p) / n + Z * Z / (4. * n * n)).sqrt() / (1. + Z * Z / n);
+    (center - half, center + half)
+}
+
+struct Row {
+    opponent: String,
+    wins: usize,
+    losses: usize,
+    ties: usize,
+    /// The points scored minus the points conceded, per round.
+    margin: f64,
+}
+impl Row {
+    fn verdict(&self) -> &'static str {
+        let (low, high) = wilson(self.wins, self.wins + self.losses);
+        if low > 0.5 {
+            "better"
+        } else if high < 0.5 {
+            "worse"
+        } else {
+            "even"
+        }
+    }
+}
+
+/// Plays the bot against every reference bot and prints a scorecard. The
+/// matches don't change the ratings.
+pub fn gauntlet(state: &mut State, bot: &str, config: &MatchConfig) {
+    let (id, name) = match state.find(bot) {
+        Some(i) => (state.clients[i].id, state.clients[i].name.clone()),
+        None => {
+            println!("no such bot {}", bot);
+            return;
+        },
+    };
+    let opponents: Vec<(usize, String)> = state.clients.iter()
+        .filter(|client| client.reference && client.id != id)
+        .map(|client| (client.id, client.name.clone()))
+        .collect();
+    if opponents.is_empty() {
+        println!("There are no reference bots.");
+        return;
+    }
+    let mut rows = Vec::new();
+    for (opponent_id, opponent) in opponents {
+        println!("{} vs {}", name, opponent);
+        match state.play_unrated(id, opponent_id, config) {
+            Some(report) => rows.push(Row {
+                opponent,
+                wins: report.wins[0],
+                losses: report.wins[1],
+                ties: report.ties,
+                margin: (report.score[0] - report.score[1]) as f64 / report.rounds.max(1) as f64,
+            }),
+            None => println!("The match against {} couldn't be played.", opponent),
+        }
+        if state.index_of(id).is_none() {
+            println!("{} disconnected.", name);
+            break;
+        }
+    }
+    println!("Scorecard for {}:", name);
+    println!("  {:<20} {:>6} {:>6} {:>6} {:>8} {:>15} {:>8}",
+             "opponent", "wins", "losses", "ties", "win rate", "95% CI", "verdict");
+    for row in &rows {
+        let decided = row.wins + row.losses;
+        let (low, high) = wilson(row.wins, decided);
+        println!("  {:<20} {:>6} {:>6} {:>6} {:>7.1}% {:>6.1}% - {:>4.1}% {:>8}",
+                 row.opponent, row.wins, row.losses, row.ties,
+                 100. * row.wins as f64 / decided.max(1) as f64,
+                 100. * low, 100. * high, row.verdict());
+    }
+    if rows.is_empty() {
+        return;
+    }
+    let count = |verdict| rows.iter().filter(|row| row.verdict() == verdict).count();
+    let margin = rows.iter().map(|row| row.margin).sum::<f64>() / rows.len() as f64;
+    println!("Better than {}, even with {} and worse than {} of {} reference bots.",
+             count("better"), count("even"), count("worse"), rows.len());
+    println!("Overall score: {:+.4} points per round.", margin);
+}
@@ -0,0 +1,51 @@
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::thread::spawn;
+use std::time::{Duration, Instant};
+
+use listen::ShutdownHandle;
+use session::SESSION_GRACE;
+use State;
+
+/// How often idle clients are pinged.
+const CHECK_INTERVAL: u64 = 10;
+
+/// Accepts new clients while the prompt is idle, regularly pings every idle
+/// client to remove the dead ones, and expires old sessions.
+pub fn health_thread(state: Arc<Mutex<State>>) -> ShutdownHandle {
+    let (shutdown_send, shutdown_recv) = channel();
+    let handle = spawn(move || {
+        let mut last_check = Instant::now();
+        loop {
+            match shutdown_recv.recv_timeout(Duration::from_millis(100)) {
+                Err(RecvTimeoutError::Timeout) => {},
+                Err(RecvTimeoutError::Disconnected) | Ok(()) => return,
+            }
+            state.lock().unwrap().print_messages();
+            if last_check.elapsed() >= Duration::new(CHECK_INTERVAL, 0) {
+                ping_idle(&state);
+                state.lock().unwrap().expire_sessions(SESSION_GRACE);
+                last_check = Instant::now();
+            }
+        }
+    });
+    ShutdownHandle::new(handle, shutdown_send)
+}
+
+/// Pings the idle clients one at a time, each checked out so that a client
+/// that is slow to answer doesn't block the state.
+fn ping_idle(state: &Mutex<State>) {
+    let ids: Vec<usize> = state.lock().unwrap().clients.iter().map(|client| client.id).collect();
+    for id in ids {
+        let mut client = {
+            let mut state = state.lock().unwrap();
+            // The client may have been removed or taken by the ladder.
+            match state.index_of(id) {
+                Some(i) => state.check_out_one(i),
+                None => continue,
+            }
+        };
+        let res = client.ping();
+        state.lock().unwrap().check_in(client, res);
+    }
+}
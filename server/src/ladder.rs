@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::thread::spawn;
+use std::time::Duration;
+use rand::{thread_rng, Rng};
+
+use battle::{self, MatchConfig, Policy};
+use listen::ShutdownHandle;
+use State;
+
+/// How long the ladder waits between matches, in milliseconds.
+const LADDER_INTERVAL: u64 = 500;
+/// The rating difference at which a pairing becomes `e` times less likely.
+const RATING_SCALE: f64 = 200.;
+
+/// Settings of the ladder and the number of matches between each pair.
+pub struct Ladder {
+    pub running: bool,
+    pub config: MatchConfig,
+    played: HashMap<(usize, usize), usize>,
+}
+impl Ladder {
+    pub fn new(policy: Policy) -> Ladder {
+        Ladder {
+            running: false,
+            config: MatchConfig::new(1000, policy),
+            played: HashMap::new(),
+        }
+    }
+    /// The number of matches the two bots have played against each other.
+    pub fn played(&self, id1: usize, id2: usize) -> usize {
+        self.played.get(&(id1.min(id2), id1.max(id2))).cloned().unwrap_or(0)
+    }
+    pub fn record(&mut self, id1: usize, id2: usize) {
+        *self.played.entry((id1.min(id2), id1.max(id2))).or_insert(0) += 1;
+    }
+}
+
+/// Picks two idle rated clients at random, preferring bots of similar rating
+/// that have played each other the least. Returns their indexes.
+fn pick(state: &State) -> Option<(usize, usize)> {
+    let config = &state.ladder.config;
+    let mut pairs = Vec::new();
+    for (i, client1) in state.clients.iter().enumerate() {
+        for (j, client2) in state.clients.iter().enumerate().skip(i + 1) {
+            if !client1.rated || !client2.rated {
+                continue;
+            }
+            if !client1.supports(&config.rules) || !client2.supports(&config.rules) {
+                continue;
+            }
+            let diff = (state.ratings.elo(client1.id) - state.ratings.elo(client2.id)).abs();
+            let played = state.ladder.played(client1.id, client2.id);
+            let weight = (-diff / RATING_SCALE).exp() / (1 + played) as f64;
+            pairs.push((i, j, weight));
+        }
+    }
+    let total: f64 = pairs.iter().map(|&(_, _, weight)| weight).sum();
+    if pairs.is_empty() || total <= 0. {
+        return None;
+    }
+    let mut choice = thread_rng().gen_range(0., total);
+    for &(i, j, weight) in &pairs {
+        if choice < weight {
+            return Some((i, j));
+        }
+        choice -= weight;
+    }
+    pairs.last().map(|&(i, j, _)| (i, j))
+}
+
+/// Plays ladder matches between idle clients while the ladder is running.
+/// The clients are taken out of the state during the match, so the prompt
+/// isn't blocked.
+pub fn ladder_thread(state: Arc<Mutex<State>>) -> ShutdownHandle {
+    let (shutdown_send, shutdown_recv) = channel();
+    let handle = spawn(move || {
+        loop {
+            match shutdown_recv.recv_timeout(Duration::from_millis(LADDER_INTERVAL)) {
+                Err(RecvTimeoutError::Timeout) => {},
+                Err(RecvTimeoutError::Disconnected) | Ok(()) => return,
+            }
+            let (mut bot1, mut bot2, config) = {
+                let mut state = state.lock().unwrap();
+                if !state.ladder.running {
+                    continue;
+                }
+                let (i, j) = match pick(&state) {
+                    Some(pair) => pair,
+                    None => continue,
+                };
+                let (bot1, bot2) = state.check_out(i, j);
+                (bot1, bot2, state.ladder.config.clone())
+            };
+            let res = battle::play_set(&mut bot1, &mut bot2, &config);
+            // Failed games are aborted and the bots pinged before taking the
+            // lock, as the bots may take a while to answer.
+            let checked = match res {
+                Ok(_) => [Ok(()), Ok(())],
+                Err(ref err) => {
+                    warn!(target: "battle", "Ladder match between {} and {} failed: {}",
+                          bot1.name, bot2.name, err);
+                    [bot1.destroy_game().and_then(|()| bot1.ping()),
+                     bot2.destroy_game().and_then(|()| bot2.ping())]
+                },
+            };
+            let mut state = state.lock().unwrap();
+            if let Ok(report) = res {
+                let total = report.total();
+                state.record(bot1.id, bot2.id, true, &total);
+                info!(target: "battle", "Ladder: {} {}-{} {} ({:.0}, {:.0})",
+                      bot1.name, total.score[0], total.score[1], bot2.name,
+                      state.ratings.elo(bot1.id), state.ratings.elo(bot2.id));
+            }
+            let [checked1, checked2] = checked;
+            state.check_in(bot1, checked1);
+            state.check_in(bot2, checked2);
+        }
+    });
+    ShutdownHandle::new(handle, shutdown_send)
+}
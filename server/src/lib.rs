@@ -0,0 +1,706 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::Ordering;
+use std::io::Result as IoResult;
+use std::mem::replace;
+use std::net::{TcpListener, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc::{Sender, Receiver, channel};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use auth::Keys;
+use bans::{Bans, ip_of};
+use client::{Client, is_valid_name};
+use battle::{Policy, MatchConfig, MatchReport};
+use rules::Ruleset;
+use ladder::Ladder;
+use limits::Limits;
+use metrics::{METRICS, inc};
+use rating::Ratings;
+use session::{Session, new_token};
+use transport::Subprocess;
+
+#[macro_use]
+extern crate log;
+extern crate hmac;
+extern crate sha2;
+extern crate rand;
+extern crate statrs;
+extern crate rustyline;
+pub mod listen;
+pub mod auth;
+pub mod bans;
+pub mod limits;
+pub mod client;
+pub mod battle;
+pub mod rules;
+pub mod rating;
+pub mod tournament;
+pub mod ladder;
+pub mod gauntlet;
+pub mod metrics;
+pub mod logging;
+pub mod session;
+pub mod health;
+pub mod commands;
+pub mod transport;
+
+#[allow(dead_code)]
+pub struct State {
+    new_clients_send: Sender<Client>,
+    new_clients: Receiver<Client>,
+    listen_messages_send: Sender<listen::ListenMessage>,
+    listen_messages: Receiver<listen::ListenMessage>,
+    shutdown_listen: Vec<listen::ShutdownHandle>,
+    pub clients: Vec<Client>,
+    /// The id given to the next new client.
+    next_id: usize,
+    /// Sessions of extended clients by token, kept after they disconnect.
+    sessions: HashMap<String, Session>,
+    policy: Policy,
+    timeout: Option<u64>,
+    rulesets: Vec<Ruleset>,
+    pub ratings: Ratings,
+    pub ladder: Ladder,
+    /// The ids, names and addresses of clients that are playing a ladder
+    /// match.
+    busy: Vec<(usize, String, String)>,
+    /// Busy clients to disconnect when their match ends.
+    kicked: HashSet<usize>,
+    /// New names of busy clients, which they take when their match ends.
+    renamed: HashMap<usize, String>,
+    /// Shared with the listeners, which refuse banned addresses.
+    bans: Arc<Mutex<Bans>>,
+    /// Shared with the listeners, which refuse connections over the limits.
+    pub limits: Arc<Mutex<Limits>>,
+    /// Shared with the listeners, which challenge clients that claim a team.
+    keys: Arc<Mutex<Keys>>,
+}
+impl State {
+    /// Creates a state without clients. It doesn't accept clients until
+    /// `listen` is called.
+    pub fn new() -> State {
+        let (new_clients_send, new_clients) = channel();
+        let (listen_messages_send, listen_messages) = channel();
+        State {
+            new_clients_send,
+            new_clients,
+            listen_messages_send,
+            listen_messages,
+            shutdown_listen: Vec::new(),
+            clients: Vec::new(),
+            next_id: 0,
+            sessions: HashMap::new(),
+            policy: Policy::ForfeitRound,
+            timeout: Some(10),
+            rulesets: Ruleset::builtin(),
+            ratings: Ratings::default(),
+            ladder: Ladder::new(Policy::ForfeitRound),
+            busy: Vec::new(),
+            kicked: HashSet::new(),
+            renamed: HashMap::new(),
+            bans: Arc::new(Mutex::new(Bans::default())),
+            limits: Arc::new(Mutex::new(Limits::default())),
+            keys: Arc::new(Mutex::new(Keys::default())),
+        }
+    }
+    /// Replaces the ban list with the one in the file, and saves it there
+    /// when it changes.
+    pub fn load_bans(&mut self, path: PathBuf) -> IoResult<()> {
+        *self.bans.lock().unwrap() = Bans::load(path)?;
+        Ok(())
+    }
+    /// Replaces the team keys with the ones in the file. Once there are keys,
+    /// only authenticated clients are rated. Returns the number of teams.
+    pub fn load_keys(&mut self, path: &Path) -> IoResult<usize> {
+        let keys = Keys::load(path)?;
+        let teams = keys.len();
+        *self.keys.lock().unwrap() = keys;
+        Ok(teams)
+    }
+    /// Starts accepting clients on the address.
+    pub fn listen<A: ToSocketAddrs + Send + 'static>(&mut self, addr: A) {
+        let handle = listen::listen_thread(
+            addr,
+            self.new_clients_send.clone(),
+            self.listen_messages_send.clone(),
+            self.bans.clone(),
+            self.limits.clone(),
+            self.keys.clone(),
+        );
+        self.shutdown_listen.push(handle);
+    }
+    /// Starts accepting clients on a listener that is already bound, such as
+    /// one on an ephemeral port.
+    pub fn listen_on(&mut self, listener: TcpListener) {
+        let handle = listen::listener_thread(
+            listener,
+            self.new_clients_send.clone(),
+            self.listen_messages_send.clone(),
+            self.bans.clone(),
+            self.limits.clone(),
+            self.keys.clone(),
+        );
+        self.shutdown_listen.push(handle);
+    }
+    /// Starts accepting clients on a Unix socket at the path.
+    #[cfg(unix)]
+    pub fn listen_unix(&mut self, path: PathBuf) {
+        let handle = listen::unix_listen_thread(
+            path,
+            self.new_clients_send.clone(),
+            self.listen_messages_send.clone(),
+            self.bans.clone(),
+            self.limits.clone(),
+            self.keys.clone(),
+        );
+        self.shutdown_listen.push(handle);
+    }
+    /// Starts a bot as a child process that plays over its stdin and stdout,
+    /// and waits for its handshake.
+    pub fn spawn(&mut self, command: &mut Command) -> IoResult<()> {
+        let desc = format!("{:?}", command);
+        let transport = Subprocess::spawn(command)?;
+        let mut client = Client::new(desc, Box::new(transport))?;
+        let keys = self.keys.lock().unwrap().clone();
+        client.authenticate(&keys)?;
+        self.add_client(client);
+        Ok(())
+    }
+    /// Starts serving the metrics over http on the address.
+    pub fn serve_metrics<A: ToSocketAddrs + Send + 'static>(&mut self, addr: A) {
+        let handle = metrics::metrics_thread(addr, self.listen_messages_send.clone());
+        self.shutdown_listen.push(handle);
+    }
+    pub fn print_messages(&mut self) {
+        while let Ok(msg) = self.listen_messages.try_recv() {
+            match msg.err {
+                Some(io) => warn!(target: "listener", "{} {}", msg.desc, io),
+                None => info!(target: "listener", "{}", msg.desc),
+            }
+        }
+        while let Ok(client) = self.new_clients.try_recv() {
+            self.add_client(client);
+        }
+    }
+    /// Adds a client that has made its handshake, unless its name is refused.
+    pub fn add_client(&mut self, mut client: Client) {
+        let res = match self.timeout {
+            Some(t) => client.set_timeout(t),
+            None => client.remove_timeout(),
+        };
+        if let Err(err) = res {
+            warn!(target: "client", "Dropping new client {}: {}", client.name, err);
+            inc(&METRICS.handshakes_failed);
+            return;
+        }
+        if !is_valid_name(&client.name) {
+            warn!(target: "client", "Rejecting client from {}: invalid name {:?}.",
+                  client.addr, client.name);
+            let _ = client.reject("invalid-name");
+            inc(&METRICS.handshakes_failed);
+            return;
+        }
+        let session = client.option("token").and_then(|token| self.sessions.get(token));
+        if let Some(session) = session {
+            if session.login != client.name || session.team != client.team {
+                warn!(target: "client", "Rejecting client from {}: the token of {} was sent by {}.",
+                      client.addr, session.name, client.name);
+                let _ = client.reject("bad-token");
+                inc(&METRICS.handshakes_failed);
+                return;
+            }
+        }
+        let session = session.map(|session| (session.id, session.name.clone()));
+        let resumed = session.is_some();
+        let (id, name) = session.unwrap_or_else(|| (self.next_id, client.name.clone()));
+        if self.bans.lock().unwrap().is_banned(&name, &client.addr) {
+            warn!(target: "client", "Rejecting client from {}: {} is banned.",
+                  client.addr, name);
+            let _ = client.reject("banned");
+            inc(&METRICS.handshakes_failed);
+            return;
+        }
+        let over_limit = {
+            let others = self.clients.iter().filter(|other| other.id != id)
+                .map(|other| ip_of(&other.addr))
+                .chain(self.busy.iter().filter(|other| other.0 != id).map(|other| ip_of(&other.2)));
+            let mut limits = self.limits.lock().unwrap();
+            limits.set_clients(others);
+            limits.check(ip_of(&client.addr))
+        };
+        // The counts left out the client being resumed, so they are put back
+        // before anything else can return.
+        self.update_client_count();
+        if let Some(reason) = over_limit {
+            warn!(target: "client", "Rejecting client from {}: {}.", client.addr, reason);
+            let _ = client.reject("over-limit");
+            inc(&METRICS.handshakes_failed);
+            return;
+        }
+        if self.is_taken(&name, id) {
+            warn!(target: "client", "Rejecting client from {}: the name {} is taken.",
+                  client.addr, name);
+            let _ = client.reject("duplicate-name");
+            inc(&METRICS.handshakes_failed);
+            return;
+        }
+        if !resumed {
+            self.next_id += 1;
+        }
+        client.id = id;
+        let login = replace(&mut client.name, name);
+        client.reference = client.option("reference").is_some();
+        client.rated = client.team.is_some() || self.keys.lock().unwrap().is_empty();
+        if client.options.is_some() {
+            let token = match client.option("token") {
+                Some(token) if resumed => String::from(token),
+                _ => new_token(),
+            };
+            let session = Session {
+                id,
+                name: client.name.clone(),
+                login: login.clone(),
+                team: client.team.clone(),
+                last_seen: Instant::now(),
+            };
+            self.sessions.entry(token.clone()).or_insert(session).last_seen = Instant::now();
+            client.token = Some(token);
+        }
+        if let Err(err) = client.accept() {
+            warn!(target: "client", "Handshake with {} failed: {}", client.name, err);
+            inc(&METRICS.handshakes_failed);
+            return;
+        }
+        inc(&METRICS.handshakes_accepted);
+        let old = self.clients.iter().position(|old| old.id == client.id);
+        match old {
+            Some(i) => {
+                info!(target: "client", "{} reconnected from {}.", client.name, client.addr);
+                self.clients[i] = client;
+            },
+            None => {
+                info!(target: "client", "{} connected from {}.", client.name, client.addr);
+                self.clients.push(client);
+            },
+        }
+        self.update_client_count();
+    }
+    /// Returns true if a client other than the one with this id has the name.
+    fn is_taken(&self, name: &str, id: usize) -> bool {
+        self.clients.iter().any(|other| other.name == name && other.id != id)
+            || self.busy.iter().any(|other| other.1 == name && other.0 != id)
+    }
+    /// Disconnects a bot. It may connect again. A bot playing on the ladder
+    /// is disconnected when its match ends.
+    pub fn kick(&mut self, bot: &str) {
+        if let Some(i) = self.find(bot) {
+            let client = self.clients.remove(i);
+            info!(target: "client", "Kicked {}.", client.name);
+            client.shutdown();
+            self.update_client_count();
+        } else if let Some(i) = self.find_busy(bot) {
+            let (id, ref name, _) = self.busy[i];
+            info!(target: "client", "{} will be kicked when its ladder match ends.", name);
+            self.kicked.insert(id);
+        } else {
+            println!("no such bot {}", bot);
+        }
+    }
+    /// Bans a name or an address, and disconnects the bots it matches.
+    pub fn ban(&mut self, entry: &str) {
+        let added = match self.bans.lock().unwrap().ban(entry) {
+            Ok(added) => added,
+            Err(err) => {
+                warn!(target: "server", "Unable to save the ban list: {}", err);
+                true
+            },
+        };
+        if !added {
+            println!("{} is already banned.", entry);
+            return;
+        }
+        info!(target: "client", "Banned {}.", entry);
+        let banned: Vec<usize> = {
+            let bans = self.bans.lock().unwrap();
+            self.clients.iter().enumerate()
+                .filter(|&(_, client)| bans.is_banned(&client.name, &client.addr))
+                .map(|(i, _)| i)
+                .collect()
+        };
+        for i in banned.into_iter().rev() {
+            let client = self.clients.remove(i);
+            info!(target: "client", "Kicked {}, who is banned.", client.name);
+            client.shutdown();
+        }
+        // Busy bots are checked against the bans when they are checked in.
+        let bans = self.bans.lock().unwrap();
+        for (_, name, _) in self.busy.iter().filter(|busy| bans.is_banned(&busy.1, &busy.2)) {
+            info!(target: "client", "{} will be kicked when its ladder match ends.", name);
+        }
+        drop(bans);
+        self.update_client_count();
+    }
+    pub fn unban(&mut self, entry: &str) {
+        match self.bans.lock().unwrap().unban(entry) {
+            Ok(true) => info!(target: "client", "Lifted the ban on {}.", entry),
+            Ok(false) => println!("{} isn't banned.", entry),
+            Err(err) => warn!(target: "server", "Unable to save the ban list: {}", err),
+        }
+    }
+    /// The banned addresses and names.
+    pub fn bans(&self) -> Vec<String> {
+        self.bans.lock().unwrap().list()
+    }
+    /// Renames a bot. Its session keeps the new name if it reconnects. A bot
+    /// playing on the ladder takes the name when its match ends.
+    pub fn rename(&mut self, bot: &str, name: &str) {
+        let (id, old) = if let Some(i) = self.find(bot) {
+            (self.clients[i].id, self.clients[i].name.clone())
+        } else if let Some(i) = self.find_busy(bot) {
+            (self.busy[i].0, self.busy[i].1.clone())
+        } else {
+            println!("no such bot {}", bot);
+            return;
+        };
+        if !is_valid_name(name) {
+            println!("{:?} is not a valid name.", name);
+            return;
+        }
+        if self.is_taken(name, id) {
+            println!("The name {} is taken.", name);
+            return;
+        }
+        for session in self.sessions.values_mut().filter(|session| session.id == id) {
+            session.name = String::from(name);
+        }
+        match self.index_of(id) {
+            Some(i) => self.clients[i].name = String::from(name),
+            None => {
+                for busy in self.busy.iter_mut().filter(|busy| busy.0 == id) {
+                    busy.1 = String::from(name);
+                }
+                self.renamed.insert(id, String::from(name));
+            },
+        }
+        info!(target: "client", "Renamed {} to {}.", old, name);
+    }
+    /// Updates the number of connected clients in the metrics and the limits.
+    fn update_client_count(&self) {
+        METRICS.clients.store(self.clients.len() + self.busy.len(), Ordering::Relaxed);
+        let ips = self.clients.iter().map(|client| ip_of(&client.addr))
+            .chain(self.busy.iter().map(|busy| ip_of(&busy.2)));
+        self.limits.lock().unwrap().set_clients(ips);
+    }
+    pub fn ping(&mut self) {
+        self.prune_dead();
+        if self.clients.is_empty() && self.busy.is_empty() {
+            println!("There are no clients.");
+        }
+        for client in &self.clients {
+            match client.team {
+                Some(ref team) => {
+                    println!("Client {} is called {} and is on team {}.",
+                             client.id, client.name, team);
+                },
+                None if !client.rated => {
+                    println!("Client {} is called {} and only plays practice matches.",
+                             client.id, client.name);
+                },
+                None => println!("Client {} is called {}.", client.id, client.name),
+            }
+        }
+        for &(id, ref name, _) in &self.busy {
+            println!("Client {} is called {} and is playing on the ladder.", id, name);
+        }
+    }
+    /// Finds a client by name, or by id if no client has that name.
+    pub fn find(&self, bot: &str) -> Option<usize> {
+        self.clients.iter().position(|client| client.name == bot)
+            .or_else(|| {
+                let id: usize = bot.parse().ok()?;
+                self.clients.iter().position(|client| client.id == id)
+            })
+    }
+    /// Finds a client that is playing on the ladder, like `find`. Returns its
+    /// index in `busy`.
+    fn find_busy(&self, bot: &str) -> Option<usize> {
+        self.busy.iter().position(|busy| busy.1 == bot)
+            .or_else(|| {
+                let id: usize = bot.parse().ok()?;
+                self.busy.iter().position(|busy| busy.0 == id)
+            })
+    }
+    /// Forgets the sessions of clients that have been disconnected for longer
+    /// than `grace`.
+    pub fn expire_sessions(&mut self, grace: Duration) {
+        let connected: HashSet<usize> = self.clients.iter().map(|client| client.id)
+            .chain(self.busy.iter().map(|busy| busy.0))
+            .collect();
+        let now = Instant::now();
+        self.sessions.retain(|_, session| {
+            if connected.contains(&session.id) {
+                session.last_seen = now;
+                true
+            } else {
+                now.duration_since(session.last_seen) < grace
+            }
+        });
+    }
+    /// Pings every client and removes the ones that don't respond.
+    pub fn prune_dead(&mut self) {
+        let all: Vec<usize> = (0..self.clients.len()).collect();
+        self.prune(&all);
+    }
+    fn prune(&mut self, indexes: &[usize]) {
+        let mut dead = Vec::new();
+        for &i in indexes {
+            let client = &mut self.clients[i];
+            if let Err(err) = client.ping() {
+                warn!(target: "client", "Removing client {}: {}", client.name, err);
+                dead.push(i);
+            }
+        }
+        self.remove_clients(dead);
+    }
+    /// Removes the clients whose connection was closed after a failed abort.
+    fn remove_closed(&mut self, indexes: &[usize]) {
+        let mut closed = Vec::new();
+        for &i in indexes.iter().filter(|&&i| self.clients[i].is_closed()) {
+            warn!(target: "client", "Removing client {}: its abort failed.", self.clients[i].name);
+            closed.push(i);
+        }
+        self.remove_clients(closed);
+    }
+    fn remove_clients(&mut self, mut indexes: Vec<usize>) {
+        indexes.sort();
+        for i in indexes.into_iter().rev() {
+            self.clients.remove(i);
+        }
+        self.update_client_count();
+    }
+    /// Accepts new clients until there are at least `count`, or until
+    /// `timeout` seconds have passed.
+    pub fn wait_for_clients(&mut self, count: usize, timeout: Option<u64>) {
+        let start = Instant::now();
+        loop {
+            self.print_messages();
+            if self.clients.len() >= count {
+                println!("There are {} clients.", self.clients.len());
+                return;
+            }
+            if let Some(secs) = timeout {
+                if start.elapsed() >= Duration::new(secs, 0) {
+                    println!("Gave up waiting with {} clients.", self.clients.len());
+                    return;
+                }
+            }
+            sleep(Duration::from_millis(100));
+        }
+    }
+    pub fn set_timeout(&mut self, timeout: Option<u64>) {
+        self.timeout = timeout;
+        let mut indexes = Vec::new();
+        for (i, client) in self.clients.iter_mut().enumerate() {
+            let client_res = match timeout {
+                Some(t) => client.set_timeout(t),
+                None => client.remove_timeout(),
+            };
+            match client_res {
+                Ok(()) => { },
+                Err(err) => {
+                    warn!(target: "client", "Removing client {}: {}", client.name, err);
+                    indexes.push(i);
+                },
+            }
+        }
+        self.remove_clients(indexes);
+    }
+    pub fn long_battle(&mut self, bot1: &str, bot2: &str, config: &MatchConfig) {
+        let bot1 = match self.find(bot1) {
+            Some(i) => i,
+            None => {
+                println!("no such bot {}", bot1);
+                return;
+            }
+        };
+        let bot2 = match self.find(bot2) {
+            Some(i) => i,
+            None => {
+                println!("no such bot {}", bot2);
+                return;
+            }
+        };
+        if bot1 == bot2 {
+            println!("same bot");
+            return;
+        }
+        self.play(bot1, bot2, config, true);
+    }
+    /// Plays a match between the clients at the two indexes and, if it
+    /// `counts`, updates their ratings. Returns the total of the set, or
+    /// `None` if it couldn't be played, in which case clients that died are
+    /// removed.
+    fn play(
+        &mut self,
+        bot1: usize,
+        bot2: usize,
+        config: &MatchConfig,
+        counts: bool,
+    ) -> Option<MatchReport> {
+        let (client1, client2) = get_two_mut(&mut self.clients, bot1, bot2);
+        for client in [&*client1, &*client2].iter() {
+            if !client.supports(&config.rules) {
+                println!("{} can't play {}.", client.name, config.rules.name);
+                return None;
+            }
+        }
+        match battle::play_set(client1, client2, config) {
+            Ok(report) => {
+                report.print(&client1.name, &client2.name);
+                println!("battle finished");
+                let total = report.total();
+                let (id1, id2) = (client1.id, client2.id);
+                let rated = client1.rated && client2.rated;
+                if counts {
+                    if !rated {
+                        println!("This was a practice match, so the ratings are unchanged.");
+                    }
+                    self.record(id1, id2, rated, &total);
+                }
+                self.remove_closed(&[bot1, bot2]);
+                Some(total)
+            },
+            Err(err) => {
+                warn!(target: "battle", "battle failed: {}", err);
+                let aborted = [client1.destroy_game(), client2.destroy_game()];
+                let mut alive = Vec::new();
+                let mut dead = Vec::new();
+                for (&i, res) in [bot1, bot2].iter().zip(aborted.iter()) {
+                    match *res {
+                        Ok(()) => alive.push(i),
+                        Err(ref err) => {
+                            warn!(target: "client", "Removing client {}: {}",
+                                  self.clients[i].name, err);
+                            dead.push(i);
+                        },
+                    }
+                }
+                for i in alive {
+                    if let Err(err) = self.clients[i].ping() {
+                        warn!(target: "client", "Removing client {}: {}", self.clients[i].name, err);
+                        dead.push(i);
+                    }
+                }
+                self.remove_clients(dead);
+                None
+            },
+        }
+    }
+    /// Plays a match between the clients with the given ids, like `play`.
+    pub fn play_ids(&mut self, id1: usize, id2: usize, config: &MatchConfig) -> Option<MatchReport> {
+        let bot1 = self.index_of(id1)?;
+        let bot2 = self.index_of(id2)?;
+        self.play(bot1, bot2, config, true)
+    }
+    /// Like `play_ids`, but leaves the ratings and the ladder alone.
+    pub fn play_unrated(&mut self, id1: usize, id2: usize, config: &MatchConfig) -> Option<MatchReport> {
+        let bot1 = self.index_of(id1)?;
+        let bot2 = self.index_of(id2)?;
+        self.play(bot1, bot2, config, false)
+    }
+    /// Updates the ladder with the result of a match, and the ratings if it
+    /// was rated.
+    fn record(&mut self, id1: usize, id2: usize, rated: bool, total: &MatchReport) {
+        let score = match total.winner() {
+            Some(0) => 1.,
+            Some(_) => 0.,
+            None => 0.5,
+        };
+        if rated {
+            self.ratings.update(id1, id2, score);
+        }
+        self.ladder.record(id1, id2);
+    }
+    /// Takes a client out of the list to use it without holding the lock on
+    /// the state.
+    fn check_out_one(&mut self, i: usize) -> Client {
+        let client = self.clients.remove(i);
+        self.busy.push((client.id, client.name.clone(), client.addr.clone()));
+        client
+    }
+    /// Takes two clients out of the list to play a match.
+    fn check_out(&mut self, bot1: usize, bot2: usize) -> (Client, Client) {
+        // The later index is taken first, so the other one stays valid.
+        if bot1 > bot2 {
+            let client1 = self.check_out_one(bot1);
+            (client1, self.check_out_one(bot2))
+        } else {
+            let client2 = self.check_out_one(bot2);
+            (self.check_out_one(bot1), client2)
+        }
+    }
+    /// Returns a client taken by `check_out`. It is dropped if `checked`, the
+    /// result of aborting its game or pinging it while it was out, failed, if
+    /// its connection was closed, or if it has reconnected in the meantime. It is disconnected if it was
+    /// kicked or banned while it was out, and a rename is applied.
+    fn check_in(&mut self, mut client: Client, checked: IoResult<()>) {
+        self.busy.retain(|busy| busy.0 != client.id);
+        if let Some(name) = self.renamed.remove(&client.id) {
+            client.name = name;
+        }
+        let kicked = self.kicked.remove(&client.id);
+        if self.index_of(client.id).is_some() {
+            self.update_client_count();
+            return;
+        }
+        let banned = self.bans.lock().unwrap().is_banned(&client.name, &client.addr);
+        if kicked || banned {
+            info!(target: "client", "Kicked {}{}.", client.name,
+                  if banned { ", who is banned" } else { "" });
+            client.shutdown();
+            self.update_client_count();
+            return;
+        }
+        if client.is_closed() {
+            warn!(target: "client", "Removing client {}: its abort failed.", client.name);
+            self.update_client_count();
+            return;
+        }
+        if let Err(err) = checked {
+            warn!(target: "client", "Removing client {}: {}", client.name, err);
+            self.update_client_count();
+            return;
+        }
+        self.clients.push(client);
+    }
+    pub fn index_of(&self, id: usize) -> Option<usize> {
+        self.clients.iter().position(|client| client.id == id)
+    }
+    pub fn shutdown(&mut self) {
+        for client in self.clients.drain(..) {
+            client.shutdown();
+        }
+        for handle in self.shutdown_listen.drain(..) {
+            handle.shutdown();
+        }
+    }
+}
+impl Default for State {
+    fn default() -> State {
+        State::new()
+    }
+}
+
+/// Borrows two distinct elements of a slice mutably.
+fn get_two_mut<T>(slice: &mut [T], a: usize, b: usize) -> (&mut T, &mut T) {
+    assert!(a != b);
+    if a < b {
+        let (left, right) = slice.split_at_mut(b);
+        (&mut left[a], &mut right[0])
+    } else {
+        let (left, right) = slice.split_at_mut(a);
+        (&mut right[0], &mut left[b])
+    }
+}
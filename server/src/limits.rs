@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use bans::canonical;
+
+/// How long an address is refused when it first connects too fast.
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+/// The longest an address is refused for connecting too fast.
+const MAX_BACKOFF: Duration = Duration::from_secs(64);
+
+/// Limits on the connections the listeners accept. The state keeps the
+/// number of connected clients up to date, so the listeners can refuse
+/// connections before the handshake.
+#[derive(Default)]
+pub struct Limits {
+    /// The most clients that may be connected at once.
+    pub max_clients: Option<usize>,
+    /// The most clients that may be connected from one address.
+    pub max_per_ip: Option<usize>,
+    /// The most connections accepted from one address per second. Addresses
+    /// that connect faster are refused for a while, which doubles each time.
+    pub accept_rate: Option<f64>,
+    clients: usize,
+    per_ip: HashMap<IpAddr, usize>,
+}
+impl Limits {
+    /// Records the connected clients, given their addresses.
+    pub fn set_clients<I: Iterator<Item = Option<IpAddr>>>(&mut self, ips: I) {
+        self.clients = 0;
+        self.per_ip.clear();
+        for ip in ips {
+            self.clients += 1;
+            if let Some(ip) = ip {
+                *self.per_ip.entry(canonical(ip)).or_insert(0) += 1;
+            }
+        }
+    }
+    /// Returns the reason a new client from the address would go over the
+    /// limits, if it would.
+    pub fn check(&self, ip: Option<IpAddr>) -> Option<String> {
+        if let Some(max) = self.max_clients {
+            if self.clients >= max {
+                return Some(format!("the server is full with {} clients", self.clients));
+            }
+        }
+        if let (Some(max), Some(ip)) = (self.max_per_ip, ip) {
+            let count = self.per_ip.get(&canonical(ip)).cloned().unwrap_or(0);
+            if count >= max {
+                return Some(format!("{} has {} clients connected", canonical(ip), count));
+            }
+        }
+        None
+    }
+}
+
+/// The accept rate of one address.
+struct Throttle {
+    /// How many connections may be accepted right now.
+    allowance: f64,
+    last: Instant,
+    blocked_until: Option<Instant>,
+    backoff: Duration,
+}
+
+/// Keeps track of how fast each address connects.
+#[derive(Default)]
+pub struct RateLimiter {
+    throttles: HashMap<IpAddr, Throttle>,
+}
+impl RateLimiter {
+    /// Records a connection from the address. If it connects faster than
+    /// `rate` per second it is refused, and the error says for how long it
+    /// will be refused if this is the first refusal of a new period.
+    pub fn accept(&mut self, ip: IpAddr, rate: f64) -> Result<(), Option<Duration>> {
+        let now = Instant::now();
+        self.throttles.retain(|_, throttle| {
+            now.duration_since(throttle.last) < MAX_BACKOFF
+                || throttle.blocked_until.is_some_and(|until| until > now)
+        });
+        let throttle = self.throttles.entry(canonical(ip)).or_insert(Throttle {
+            allowance: rate.max(1.),
+            last: now,
+            blocked_until: None,
+            backoff: MIN_BACKOFF,
+        });
+        if let Some(until) = throttle.blocked_until {
+            if until > now {
+                return Err(None);
+            }
+            // It is forgiven once it stays under the rate for as long again.
+            if now.duration_since(until) >= throttle.backoff {
+                throttle.blocked_until = None;
+                throttle.backoff = MIN_BACKOFF;
+            }
+        }
+        let elapsed = now.duration_since(throttle.last).as_secs_f64();
+        throttle.allowance = (throttle.allowance + elapsed * rate).min(rate.max(1.));
+        throttle.last = now;
+        if throttle.allowance < 1. {
+            let backoff = throttle.backoff;
+            throttle.blocked_until = Some(now + backoff);
+            throttle.backoff = (backoff * 2).min(MAX_BACKOFF);
+            return Err(Some(backoff));
+        }
+        throttle.allowance -= 1.;
+        Ok(())
+    }
+}
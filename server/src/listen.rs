@@ -1,9 +1,19 @@
-use std::sync::mpsc::{channel, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
 use std::net::{TcpListener, ToSocketAddrs};
-use std::io::Error as IoError;
+use std::io::{Result as IoResult, Error as IoError, ErrorKind};
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+#[cfg(unix)]
+use std::path::PathBuf;
 use std::thread::{JoinHandle, spawn, yield_now};
 
+use auth::Keys;
+use bans::{Bans, canonical, ip_of};
+use limits::{Limits, RateLimiter};
 use client::Client;
+use transport::Transport;
+use metrics::{METRICS, inc};
 
 pub struct ListenMessage {
     pub desc: &'static str,
@@ -28,6 +38,12 @@ pub struct ShutdownHandle {
     send: Sender<()>,
 }
 impl ShutdownHandle {
+    pub fn new(handle: JoinHandle<()>, send: Sender<()>) -> ShutdownHandle {
+        ShutdownHandle {
+            handle,
+            send,
+        }
+    }
     pub fn shutdown(self) {
         let _ = self.send.send(());
         let _ = self.handle.join();
@@ -37,7 +53,10 @@ impl ShutdownHandle {
 pub fn listen_thread<A: ToSocketAddrs + Send + 'static>(
     addr: A,
     new_clients: Sender<Client>,
-    messages: Sender<ListenMessage>
+    messages: Sender<ListenMessage>,
+    bans: Arc<Mutex<Bans>>,
+    limits: Arc<Mutex<Limits>>,
+    keys: Arc<Mutex<Keys>>,
 ) -> ShutdownHandle {
     let (shutdown_send, shutdown_recv) = channel();
     let handle = spawn(move || {
@@ -48,53 +67,193 @@ pub fn listen_thread<A: ToSocketAddrs + Send + 'static>(
                 return;
             },
         };
-        match listen.set_nonblocking(true) {
-            Ok(()) => {},
+        accept_clients(listen, new_clients, messages, bans, limits, keys, shutdown_recv);
+    });
+    ShutdownHandle::new(handle, shutdown_send)
+}
+
+/// Accepts clients on a Unix socket at the path.
+#[cfg(unix)]
+pub fn unix_listen_thread(
+    path: PathBuf,
+    new_clients: Sender<Client>,
+    messages: Sender<ListenMessage>,
+    bans: Arc<Mutex<Bans>>,
+    limits: Arc<Mutex<Limits>>,
+    keys: Arc<Mutex<Keys>>,
+) -> ShutdownHandle {
+    let (shutdown_send, shutdown_recv) = channel();
+    let handle = spawn(move || {
+        let listen = match UnixListener::bind(&path) {
+            Ok(listen) => listen,
             Err(err) => {
-                messages.send(ListenMessage::new(
-                        "Failed setting nonblocking", err)).unwrap();
+                messages.send(ListenMessage::new("Unable to listen on unix socket", err)).unwrap();
                 return;
-            }
+            },
+        };
+        accept_clients(listen, new_clients, messages, bans, limits, keys, shutdown_recv);
+    });
+    ShutdownHandle::new(handle, shutdown_send)
+}
+
+/// Checks a new connection against the bans and the limits, before the
+/// handshake. The error is `None` if the address was already told that it
+/// connects too fast.
+fn check_connection(
+    addr: &str,
+    bans: &Mutex<Bans>,
+    limits: &Mutex<Limits>,
+    limiter: &mut RateLimiter,
+) -> Result<(), Option<IoError>> {
+    let refuse = |reason: String| Err(Some(IoError::new(ErrorKind::PermissionDenied, reason)));
+    let ip = ip_of(addr);
+    if let Some(ip) = ip {
+        if bans.lock().unwrap().is_ip_banned(ip) {
+            return refuse(format!("{} is banned", canonical(ip)));
+        }
+    }
+    let limits = limits.lock().unwrap();
+    if let (Some(rate), Some(ip)) = (limits.accept_rate, ip) {
+        match limiter.accept(ip, rate) {
+            Ok(()) => {},
+            Err(Some(backoff)) => {
+                return refuse(format!("{} connects too fast and is refused for {} s",
+                                      canonical(ip), backoff.as_secs()));
+            },
+            Err(None) => return Err(None),
+        }
+    }
+    match limits.check(ip) {
+        Some(reason) => refuse(reason),
+        None => Ok(()),
+    }
+}
+
+/// A listener that the clients of `listen_thread` can come from.
+trait Listener {
+    fn set_nonblocking(&self, nonblocking: bool) -> IoResult<()>;
+    /// Accepts a connection, along with a description of where it came from.
+    fn accept_transport(&self) -> IoResult<(Box<dyn Transport>, String)>;
+}
+impl Listener for TcpListener {
+    fn set_nonblocking(&self, nonblocking: bool) -> IoResult<()> {
+        TcpListener::set_nonblocking(self, nonblocking)
+    }
+    fn accept_transport(&self) -> IoResult<(Box<dyn Transport>, String)> {
+        let (stream, addr) = self.accept()?;
+        Ok((Box::new(stream), addr.to_string()))
+    }
+}
+#[cfg(unix)]
+impl Listener for UnixListener {
+    fn set_nonblocking(&self, nonblocking: bool) -> IoResult<()> {
+        UnixListener::set_nonblocking(self, nonblocking)
+    }
+    fn accept_transport(&self) -> IoResult<(Box<dyn Transport>, String)> {
+        let (stream, addr) = self.accept()?;
+        let addr = match addr.as_pathname() {
+            Some(path) => path.display().to_string(),
+            None => String::from("unix socket"),
+        };
+        Ok((Box::new(stream), addr))
+    }
+}
+
+/// Like `listen_thread`, but with a listener that is already bound.
+pub fn listener_thread(
+    listen: TcpListener,
+    new_clients: Sender<Client>,
+    messages: Sender<ListenMessage>,
+    bans: Arc<Mutex<Bans>>,
+    limits: Arc<Mutex<Limits>>,
+    keys: Arc<Mutex<Keys>>,
+) -> ShutdownHandle {
+    let (shutdown_send, shutdown_recv) = channel();
+    let handle = spawn(move || {
+        accept_clients(listen, new_clients, messages, bans, limits, keys, shutdown_recv);
+    });
+    ShutdownHandle::new(handle, shutdown_send)
+}
+
+/// Reads the handshake and checks the challenge of a new connection on a
+/// thread of its own, so a slow client doesn't hold up the others.
+fn handshake_thread(
+    addr: String,
+    stream: Box<dyn Transport>,
+    keys: Keys,
+    new_clients: Sender<Client>,
+    messages: Sender<ListenMessage>,
+) {
+    spawn(move || {
+        let res = Client::new(addr, stream)
+            .and_then(|mut client| client.authenticate(&keys).map(|()| client));
+        // The server may have shut down in the meantime.
+        match res {
+            Ok(client) => {
+                let _ = new_clients.send(client);
+            },
+            Err(err) => {
+                inc(&METRICS.handshakes_failed);
+                let _ = messages.send(ListenMessage::new("Handshake failed.", err));
+            },
         }
-        loop {
-            match listen.accept() {
-                Ok((stream, addr)) => {
-                    match Client::new(addr, stream) {
-                        Ok(client) => {
-                            new_clients.send(client).unwrap();
-                        },
-                        Err(err) => {
-                            messages.send(ListenMessage::new(
-                                    "Handshake failed.", err)).unwrap();
-                        },
-                    };
-                },
-                Err(err) => {
-                    if err.kind() == ::std::io::ErrorKind::WouldBlock {
-                        yield_now();
-                    } else {
-                        messages.send(ListenMessage::new(
-                                "Error while listening for new clients.", err)).unwrap();
-                        return;
+    });
+}
+
+fn accept_clients<L: Listener>(
+    listen: L,
+    new_clients: Sender<Client>,
+    messages: Sender<ListenMessage>,
+    bans: Arc<Mutex<Bans>>,
+    limits: Arc<Mutex<Limits>>,
+    keys: Arc<Mutex<Keys>>,
+    shutdown_recv: Receiver<()>,
+) {
+    let mut limiter = RateLimiter::default();
+    match listen.set_nonblocking(true) {
+        Ok(()) => {},
+        Err(err) => {
+            messages.send(ListenMessage::new(
+                    "Failed setting nonblocking", err)).unwrap();
+            return;
+        }
+    }
+    loop {
+        match listen.accept_transport() {
+            Ok((stream, addr)) => {
+                let refused = check_connection(&addr, &bans, &limits, &mut limiter);
+                if let Err(err) = refused {
+                    inc(&METRICS.handshakes_failed);
+                    if let Some(err) = err {
+                        messages.send(ListenMessage::new("Refused connection.", err)).unwrap();
                     }
-                },
-            }
-            match shutdown_recv.try_recv() {
-                Err(TryRecvError::Empty) => {},
-                Err(TryRecvError::Disconnected) => {
-                    messages.send(ListenMessage::new_str(
-                            "Shutdown disconnected.")).unwrap();
-                    return;
-                },
-                Ok(()) => {
+                } else {
+                    // The keys are copied so the state isn't blocked while
+                    // the client answers the challenge.
+                    let keys = keys.lock().unwrap().clone();
+                    handshake_thread(addr, stream, keys, new_clients.clone(), messages.clone());
+                }
+            },
+            Err(err) => {
+                if err.kind() == ErrorKind::WouldBlock {
+                    yield_now();
+                } else {
+                    messages.send(ListenMessage::new(
+                            "Error while listening for new clients.", err)).unwrap();
                     return;
-                },
-            }
+                }
+            },
+        }
+        match shutdown_recv.try_recv() {
+            Err(TryRecvError::Empty) => {},
+            Err(TryRecvError::Disconnected) => {
+                messages.send(ListenMessage::new_str(
+                        "Shutdown disconnected.")).unwrap();
+                return;
+            },
+            Ok(()) => {
+                return;
+            },
         }
-    });
-    ShutdownHandle {
-        handle,
-        send: shutdown_send,
     }
 }
-
@@ -0,0 +1,150 @@
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use log::{self, Log, Level, LevelFilter, Metadata, Record};
+
+/// The log targets used by the server.
+pub const TARGETS: &[&str] = &["server", "listener", "client", "battle"];
+pub const LEVELS: &[&str] = &["off", "error", "warn", "info", "debug", "trace"];
+/// The most lines kept while the console is held. Older lines are dropped.
+const MAX_HELD: usize = 1000;
+
+/// Prints log messages to the console, and writes them as JSON lines to a
+/// file. Each target has its own level.
+pub struct Logger {
+    levels: Mutex<Vec<LevelFilter>>,
+    file: Mutex<Option<File>>,
+    console: Mutex<Console>,
+}
+
+/// Lines for the console that wait while the prompt is shown, as printing
+/// them would garble it.
+struct Console {
+    held: bool,
+    lines: VecDeque<String>,
+    dropped: usize,
+}
+
+static LOGGER: Logger = Logger {
+    levels: Mutex::new(Vec::new()),
+    file: Mutex::new(None),
+    console: Mutex::new(Console {
+        held: false,
+        lines: VecDeque::new(),
+        dropped: 0,
+    }),
+};
+
+/// Installs the logger with every target at the info level, appending to
+/// the file at `path`.
+pub fn init(path: &str) {
+    *LOGGER.levels.lock().unwrap() = vec![LevelFilter::Info; TARGETS.len()];
+    match OpenOptions::new().create(true).append(true).open(path) {
+        Ok(file) => *LOGGER.file.lock().unwrap() = Some(file),
+        Err(err) => println!("Unable to open log file {}: {}", path, err),
+    }
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(LevelFilter::Info);
+    }
+}
+
+/// Sets the level of one target, or of all targets if `target` is `None`.
+pub fn set_level(target: Option<&str>, level: LevelFilter) {
+    let mut levels = LOGGER.levels.lock().unwrap();
+    for (i, &name) in TARGETS.iter().enumerate() {
+        if target.is_none_or(|target| target == name) {
+            levels[i] = level;
+        }
+    }
+    log::set_max_level(levels.iter().cloned().max().unwrap_or(LevelFilter::Off));
+}
+
+/// Keeps console lines back until `release_console` is called. The REPL does
+/// this while it shows the prompt.
+pub fn hold_console() {
+    LOGGER.console.lock().unwrap().held = true;
+}
+
+/// Prints the lines that were kept back, and prints new lines right away.
+pub fn release_console() {
+    let mut console = LOGGER.console.lock().unwrap();
+    console.held = false;
+    if console.dropped > 0 {
+        println!("({} log messages were dropped)", console.dropped);
+        console.dropped = 0;
+    }
+    for line in console.lines.drain(..) {
+        println!("{}", line);
+    }
+}
+
+/// The level of each target.
+pub fn levels() -> Vec<(&'static str, LevelFilter)> {
+    let levels = LOGGER.levels.lock().unwrap();
+    TARGETS.iter().cloned().zip(levels.iter().cloned()).collect()
+}
+
+fn target_level(target: &str) -> LevelFilter {
+    let levels = LOGGER.levels.lock().unwrap();
+    let i = TARGETS.iter().position(|&name| name == target).unwrap_or(0);
+    levels.get(i).cloned().unwrap_or(LevelFilter::Off)
+}
+
+/// Quotes a string for JSON.
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= target_level(metadata.target())
+    }
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = record.args().to_string();
+        let line = match record.level() {
+            Level::Info => message.clone(),
+            level => format!("{} [{}] {}", level, record.target(), message),
+        };
+        {
+            let mut console = self.console.lock().unwrap();
+            if console.held {
+                if console.lines.len() == MAX_HELD {
+                    console.lines.pop_front();
+                    console.dropped += 1;
+                }
+                console.lines.push_back(line);
+            } else {
+                println!("{}", line);
+            }
+        }
+        if let Some(ref mut file) = *self.file.lock().unwrap() {
+            let time = SystemTime::now().duration_since(UNIX_EPOCH)
+                .map(|time| time.as_secs() as f64 + f64::from(time.subsec_millis()) / 1000.)
+                .unwrap_or(0.);
+            let _ = writeln!(file, "{{\"time\":{:.3},\"level\":{},\"target\":{},\"message\":{}}}",
+                             time, json_string(&record.level().to_string()),
+                             json_string(record.target()), json_string(&message));
+        }
+    }
+    fn flush(&self) {
+        if let Some(ref mut file) = *self.file.lock().unwrap() {
+            let _ = file.flush();
+        }
+    }
+}
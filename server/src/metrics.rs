@@ -0,0 +1,194 @@
+use std::fmt::Write as FmtWrite;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Sender, TryRecvError};
+use std::thread::{sleep, spawn};
+use std::time::Duration;
+
+use listen::{ListenMessage, ShutdownHandle};
+
+/// The upper bounds of the move latency buckets, in seconds.
+const LATENCY_BUCKETS: [f64; 10] = [0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1., 5.];
+
+struct Histogram {
+    buckets: [usize; 10],
+    count: usize,
+    sum: f64,
+}
+
+/// Statistics about a single bot.
+struct BotMetrics {
+    name: String,
+    latency: Histogram,
+    timeouts: usize,
+}
+
+/// Counters and gauges exported on the metrics endpoint.
+pub struct Metrics {
+    pub clients: AtomicUsize,
+    pub handshakes_accepted: AtomicUsize,
+    pub handshakes_failed: AtomicUsize,
+    pub matches_started: AtomicUsize,
+    pub matches_finished: AtomicUsize,
+    pub matches_failed: AtomicUsize,
+    pub rounds: AtomicUsize,
+    bots: Mutex<Vec<BotMetrics>>,
+}
+
+pub static METRICS: Metrics = Metrics {
+    clients: AtomicUsize::new(0),
+    handshakes_accepted: AtomicUsize::new(0),
+    handshakes_failed: AtomicUsize::new(0),
+    matches_started: AtomicUsize::new(0),
+    matches_finished: AtomicUsize::new(0),
+    matches_failed: AtomicUsize::new(0),
+    rounds: AtomicUsize::new(0),
+    bots: Mutex::new(Vec::new()),
+};
+
+/// Adds one to a counter.
+pub fn inc(counter: &AtomicUsize) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+impl Metrics {
+    fn with_bot<F: FnOnce(&mut BotMetrics)>(&self, name: &str, f: F) {
+        let mut bots = self.bots.lock().unwrap();
+        let i = match bots.iter().position(|bot| bot.name == name) {
+            Some(i) => i,
+            None => {
+                bots.push(BotMetrics {
+                    name: String::from(name),
+                    latency: Histogram { buckets: [0; 10], count: 0, sum: 0. },
+                    timeouts: 0,
+                });
+                bots.len() - 1
+            },
+        };
+        f(&mut bots[i]);
+    }
+    /// Records how long a bot took to send a move.
+    pub fn move_latency(&self, name: &str, latency: Duration) {
+        let secs = latency.as_secs() as f64 + f64::from(latency.subsec_nanos()) / 1e9;
+        self.with_bot(name, |bot| {
+            let latency = &mut bot.latency;
+            for (bucket, &bound) in latency.buckets.iter_mut().zip(&LATENCY_BUCKETS) {
+                if secs <= bound {
+                    *bucket += 1;
+                }
+            }
+            latency.count += 1;
+            latency.sum += secs;
+        });
+    }
+    pub fn timeout(&self, name: &str) {
+        self.with_bot(name, |bot| bot.timeouts += 1);
+    }
+    /// Writes the metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let counters = [
+            ("rps_clients", "gauge", "Connected clients.", &self.clients),
+            ("rps_handshakes_accepted_total", "counter", "Accepted handshakes.",
+             &self.handshakes_accepted),
+            ("rps_handshakes_failed_total", "counter", "Failed or rejected handshakes.",
+             &self.handshakes_failed),
+            ("rps_matches_started_total", "counter", "Matches started.", &self.matches_started),
+            ("rps_matches_finished_total", "counter", "Matches finished.", &self.matches_finished),
+            ("rps_matches_failed_total", "counter", "Matches aborted by an error.",
+             &self.matches_failed),
+            ("rps_rounds_total", "counter", "Rounds played.", &self.rounds),
+        ];
+        for &(name, kind, help, value) in &counters {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "{} {}", name, value.load(Ordering::Relaxed));
+        }
+        let bots = self.bots.lock().unwrap();
+        let _ = writeln!(out, "# HELP rps_move_latency_seconds Time taken by a bot to move.");
+        let _ = writeln!(out, "# TYPE rps_move_latency_seconds histogram");
+        for bot in bots.iter() {
+            let name = escape(&bot.name);
+            for (count, bound) in bot.latency.buckets.iter().zip(&LATENCY_BUCKETS) {
+                let _ = writeln!(out, "rps_move_latency_seconds_bucket{{bot=\"{}\",le=\"{}\"}} {}",
+                                 name, bound, count);
+            }
+            let _ = writeln!(out, "rps_move_latency_seconds_bucket{{bot=\"{}\",le=\"+Inf\"}} {}",
+                             name, bot.latency.count);
+            let _ = writeln!(out, "rps_move_latency_seconds_sum{{bot=\"{}\"}} {}",
+                             name, bot.latency.sum);
+            let _ = writeln!(out, "rps_move_latency_seconds_count{{bot=\"{}\"}} {}",
+                             name, bot.latency.count);
+        }
+        let _ = writeln!(out, "# HELP rps_timeouts_total Moves that timed out.");
+        let _ = writeln!(out, "# TYPE rps_timeouts_total counter");
+        for bot in bots.iter() {
+            let _ = writeln!(out, "rps_timeouts_total{{bot=\"{}\"}} {}",
+                             escape(&bot.name), bot.timeouts);
+        }
+        out
+    }
+}
+
+/// Escapes a label value.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn respond(stream: TcpStream) -> ::std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::new(1, 0)))?;
+    stream.set_write_timeout(Some(Duration::new(1, 0)))?;
+    let mut request = String::new();
+    BufReader::new(&stream).read_line(&mut request)?;
+    let (status, body) = match request.split_whitespace().nth(1) {
+        Some("/metrics") => ("200 OK", METRICS.render()),
+        _ => ("404 Not Found", String::from("Not found.\n")),
+    };
+    let mut stream = stream;
+    write!(stream, "HTTP/1.0 {}\r\nContent-Type: text/plain; version=0.0.4\r\n\
+                    Content-Length: {}\r\n\r\n{}", status, body.len(), body)?;
+    stream.flush()
+}
+
+/// Serves the metrics over http at `/metrics`.
+pub fn metrics_thread<A: ToSocketAddrs + Send + 'static>(
+    addr: A,
+    messages: Sender<ListenMessage>,
+) -> ShutdownHandle {
+    let (shutdown_send, shutdown_recv) = channel();
+    let handle = spawn(move || {
+        let listen = match TcpListener::bind(addr).and_then(|listen| {
+            listen.set_nonblocking(true)?;
+            Ok(listen)
+        }) {
+            Ok(listen) => listen,
+            Err(err) => {
+                let _ = messages.send(ListenMessage::new("Unable to start metrics server", err));
+                return;
+            },
+        };
+        loop {
+            match listen.accept() {
+                Ok((stream, _)) => {
+                    let _ = stream.set_nonblocking(false);
+                    let _ = respond(stream);
+                },
+                Err(ref err) if err.kind() == ::std::io::ErrorKind::WouldBlock => {
+                    sleep(Duration::from_millis(50));
+                },
+                Err(err) => {
+                    let _ = messages.send(ListenMessage::new(
+                            "Error while serving metrics.", err));
+                    return;
+                },
+            }
+            match shutdown_recv.try_recv() {
+                Err(TryRecvError::Empty) => {},
+                Err(TryRecvError::Disconnected) | Ok(()) => return,
+            }
+        }
+    });
+    ShutdownHandle::new(handle, shutdown_send)
+}
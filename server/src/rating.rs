@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+
+/// The rating of a bot that hasn't played yet.
+pub const INITIAL_RATING: f64 = 1500.;
+/// How much a single match can move a rating.
+const K_FACTOR: f64 = 32.;
+
+#[derive(Clone,Copy,Debug)]
+pub struct Rating {
+    pub elo: f64,
+    /// The number of rated matches played.
+    pub matches: usize,
+}
+
+/// Elo ratings of bots by their id, so they are kept when a bot reconnects
+/// with its session token.
+#[derive(Default)]
+pub struct Ratings {
+    ratings: HashMap<usize, Rating>,
+}
+impl Ratings {
+    pub fn get(&self, id: usize) -> Rating {
+        self.ratings.get(&id).cloned().unwrap_or(Rating {
+            elo: INITIAL_RATING,
+            matches: 0,
+        })
+    }
+    pub fn elo(&self, id: usize) -> f64 {
+        self.get(id).elo
+    }
+    /// Updates both ratings after a match. The score is 1 if the first bot
+    /// won, 0 if it lost and 0.5 on a tie.
+    pub fn update(&mut self, id1: usize, id2: usize, score: f64) {
+        let mut rating1 = self.get(id1);
+        let mut rating2 = self.get(id2);
+        let change = K_FACTOR * (score - expected_score(rating1.elo, rating2.elo));
+        rating1.elo += change;
+        rating2.elo -= change;
+        rating1.matches += 1;
+        rating2.matches += 1;
+        self.ratings.insert(id1, rating1);
+        self.ratings.insert(id2, rating2);
+    }
+}
+
+/// The expected score of a bot rated `elo` against one rated `other`.
+pub fn expected_score(elo: f64, other: f64) -> f64 {
+    1. / (1. + 10f64.powf((other - elo) / 400.))
+}
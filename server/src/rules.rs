@@ -0,0 +1,158 @@
+use std::io::{Result as IoResult, Error as IoError, ErrorKind};
+use rand::Rng;
+
+/// The most moves a ruleset can have. The payoff matrix of more moves doesn't
+/// fit in the line that describes the ruleset to clients.
+const MAX_MOVES: usize = 8;
+/// The longest description of a ruleset, which leaves room for the seed in
+/// the line sent at the start of each game.
+const MAX_DESCRIPTION: usize = 255 - " seed=18446744073709551615".len();
+
+/// A move, given by its index in the ruleset.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub struct Move(pub usize);
+
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum GameOutcome {
+    Win, Lose, Tie,
+}
+
+/// The moves of a game and how they score against each other.
+#[derive(Clone,Debug)]
+pub struct Ruleset {
+    pub name: String,
+    pub move_names: Vec<String>,
+    /// The byte sent for each move. These are lowercase letters, so the end
+    /// of a game can be marked by sending the uppercase letter.
+    pub symbols: Vec<u8>,
+    /// `payoff[a][b]` is the points scored by playing `a` against `b`.
+    pub payoff: Vec<Vec<i32>>,
+}
+
+impl Ruleset {
+    /// Creates a ruleset from its symbols and the rows of the payoff matrix,
+    /// given as comma separated numbers.
+    pub fn new(name: &str, symbols: &str, payoff: &str) -> Result<Ruleset, String> {
+        let symbols = symbols.as_bytes().to_vec();
+        for (i, &symbol) in symbols.iter().enumerate() {
+            if !symbol.is_ascii_lowercase() || b"nxe".contains(&symbol) {
+                return Err(format!("{} can't be used as a move.", symbol as char));
+            }
+            if symbols[..i].contains(&symbol) {
+                return Err(format!("{} is used twice.", symbol as char));
+            }
+        }
+        if symbols.len() < 2 {
+            return Err(String::from("There must be at least two moves."));
+        }
+        if symbols.len() > MAX_MOVES {
+            return Err(format!("There can be at most {} moves.", MAX_MOVES));
+        }
+        let scores = payoff.split(',')
+            .map(|score| score.trim().parse::<i32>())
+            .collect::<Result<Vec<i32>, _>>()
+            .map_err(|_| format!("{} is not a list of numbers.", payoff))?;
+        if scores.len() != symbols.len() * symbols.len() {
+            return Err(format!("The payoff matrix must have {} entries.",
+                               symbols.len() * symbols.len()));
+        }
+        let rules = Ruleset {
+            name: String::from(name),
+            move_names: symbols.iter().map(|&symbol| (symbol as char).to_string()).collect(),
+            payoff: scores.chunks(symbols.len()).map(|row| row.to_vec()).collect(),
+            symbols,
+        };
+        if rules.describe().len() > MAX_DESCRIPTION {
+            return Err(format!("The description of the ruleset is longer than {} bytes.",
+                               MAX_DESCRIPTION));
+        }
+        Ok(rules)
+    }
+    fn with_names(mut self, names: &[&str]) -> Ruleset {
+        self.move_names = names.iter().map(|&name| String::from(name)).collect();
+        self
+    }
+    /// The built in rulesets: `rps`, `weighted` where winning with rock
+    /// scores two points, and `rpsls` which adds lizard and spock.
+    pub fn builtin() -> Vec<Ruleset> {
+        let rps = ["Rock", "Paper", "Scissor"];
+        vec![
+            Ruleset::new("rps", "rps", "0,0,1, 1,0,0, 0,1,0")
+                .unwrap().with_names(&rps),
+            Ruleset::new("weighted", "rps", "0,0,2, 1,0,0, 0,1,0")
+                .unwrap().with_names(&rps),
+            Ruleset::new("rpsls", "rpslk",
+                         "0,0,1,1,0, 1,0,0,0,1, 0,1,0,1,0, 0,1,0,0,1, 1,0,1,0,0")
+                .unwrap().with_names(&["Rock", "Paper", "Scissor", "Lizard", "Spock"]),
+        ]
+    }
+    pub fn moves(&self) -> usize {
+        self.symbols.len()
+    }
+    /// Returns true if clients that don't know about rulesets can play this
+    /// game, which is the case when the moves are the usual r, p and s.
+    pub fn is_classic(&self) -> bool {
+        self.symbols == b"rps"
+    }
+    pub fn parse(&self, byte: u8) -> IoResult<Move> {
+        match self.symbols.iter().position(|&symbol| symbol == byte) {
+            Some(i) => Ok(Move(i)),
+            None => Err(IoError::new(ErrorKind::InvalidData,
+                format!("byte {} is not one of {}", byte,
+                        String::from_utf8_lossy(&self.symbols)))),
+        }
+    }
+    pub fn symbol(&self, m: Move) -> u8 {
+        self.symbols[m.0]
+    }
+    pub fn name_of(&self, m: Move) -> &str {
+        &self.move_names[m.0]
+    }
+    pub fn score(&self, m: Move, other: Move) -> i32 {
+        self.payoff[m.0][other.0]
+    }
+    /// Returns win if `m` wins.
+    pub fn outcome(&self, m: Move, other: Move) -> GameOutcome {
+        let diff = self.score(m, other) - self.score(other, m);
+        if diff > 0 {
+            GameOutcome::Win
+        } else if diff < 0 {
+            GameOutcome::Lose
+        } else {
+            GameOutcome::Tie
+        }
+    }
+    pub fn random<R: Rng>(&self, rng: &mut R) -> Move {
+        Move(rng.gen_range(0, self.moves()))
+    }
+    /// Returns the move that does the worst against `other`.
+    pub fn weakest_against(&self, other: Move) -> Move {
+        (0..self.moves())
+            .map(Move)
+            .min_by_key(|&m| self.score(m, other) - self.score(other, m))
+            .unwrap()
+    }
+    /// The variance of the score difference in one round, when both players
+    /// pick uniformly random moves. The mean is zero.
+    pub fn variance(&self) -> f64 {
+        let n = self.moves();
+        let mut sum = 0f64;
+        for a in 0..n {
+            for b in 0..n {
+                let diff = self.score(Move(a), Move(b)) - self.score(Move(b), Move(a));
+                sum += f64::from(diff * diff);
+            }
+        }
+        sum / (n * n) as f64
+    }
+    /// Describes the ruleset to clients that asked for it, for example
+    /// `rules=rps moves=rps payoff=0,0,1,1,0,0,0,1,0`.
+    pub fn describe(&self) -> String {
+        let payoff: Vec<String> = self.payoff.iter()
+            .flat_map(|row| row.iter())
+            .map(|score| score.to_string())
+            .collect();
+        format!("rules={} moves={} payoff={}",
+                self.name, String::from_utf8_lossy(&self.symbols), payoff.join(","))
+    }
+}
@@ -0,0 +1,24 @@
+use std::time::{Duration, Instant};
+
+use rand::{thread_rng, Rng};
+
+/// How long the session of a disconnected client is kept.
+pub const SESSION_GRACE: Duration = Duration::from_secs(3600);
+
+/// What the server remembers about an extended client, so it keeps its
+/// identity if it reconnects with the token. Only a client that connects
+/// with the same name and team may resume the session.
+pub struct Session {
+    pub id: usize,
+    pub name: String,
+    /// The name the client connected with, which a rename leaves alone.
+    pub login: String,
+    pub team: Option<String>,
+    /// When the client was last seen connected.
+    pub last_seen: Instant,
+}
+
+pub fn new_token() -> String {
+    let mut rng = thread_rng();
+    format!("{:016x}{:016x}", rng.gen::<u64>(), rng.gen::<u64>())
+}
@@ -0,0 +1,337 @@
+use std::cmp::Ordering;
+
+use battle::MatchConfig;
+use State;
+
+#[derive(Clone,Copy,Debug)]
+pub enum Format {
+    /// Swiss pairing for the given number of rounds.
+    Swiss(usize),
+    SingleElimination,
+    DoubleElimination,
+}
+
+struct Player {
+    id: usize,
+    name: String,
+    /// The seed, where 0 is the highest rated bot.
+    seed: usize,
+    /// The opponents played, and the score against each of them.
+    results: Vec<(usize, f64)>,
+    byes: usize,
+    losses: usize,
+}
+impl Player {
+    fn points(&self) -> f64 {
+        self.byes as f64 + self.results.iter().map(|&(_, score)| score).sum::<f64>()
+    }
+    fn has_played(&self, other: usize) -> bool {
+        self.results.iter().any(|&(opponent, _)| opponent == other)
+    }
+    fn label(&self) -> String {
+        format!("[{}] {}", self.seed + 1, self.name)
+    }
+}
+
+/// Runs a tournament between every connected bot that can play the rules
+/// of the config. Bots are seeded by rating.
+pub fn tournament(state: &mut State, format: Format, config: &MatchConfig) {
+    let mut entrants: Vec<(usize, String)> = state.clients.iter()
+        .filter(|client| client.supports(&config.rules))
+        .map(|client| (client.id, client.name.clone()))
+        .collect();
+    if entrants.len() < 2 {
+        println!("A tournament needs at least two bots.");
+        return;
+    }
+    entrants.sort_by(|a, b| {
+        let (a, b) = (state.ratings.elo(a.0), state.ratings.elo(b.0));
+        b.partial_cmp(&a).unwrap_or(Ordering::Equal)
+    });
+    let mut players: Vec<Player> = entrants.into_iter()
+        .enumerate()
+        .map(|(seed, (id, name))| Player {
+            id,
+            name,
+            seed,
+            results: Vec::new(),
+            byes: 0,
+            losses: 0,
+        })
+        .collect();
+    println!("Seeds:");
+    for player in &players {
+        println!("  {} ({:.0})", player.label(), state.ratings.elo(player.id));
+    }
+    match format {
+        Format::Swiss(stages) => swiss(state, &mut players, stages, config),
+        Format::SingleElimination => single_elimination(state, &mut players, config),
+        Format::DoubleElimination => double_elimination(state, &mut players, config),
+    }
+}
+
+/// Plays a match between two players and records the result. Returns the
+/// winner, or `None` on a tie. A bot that disconnects loses.
+fn play(state: &mut State, players: &mut [Player], a: usize, b: usize, config: &MatchConfig)
+    -> Option<usize>
+{
+    println!("{} vs {}", players[a].label(), players[b].label());
+    let winner = match state.play_ids(players[a].id, players[b].id, config) {
+        Some(report) => report.winner().map(|winner| [a, b][winner]),
+        None => {
+            let a_alive = state.index_of(players[a].id).is_some();
+            let b_alive = state.index_of(players[b].id).is_some();
+            match (a_alive, b_alive) {
+                (true, false) => Some(a),
+                (false, true) => Some(b),
+                _ => None,
+            }
+        },
+    };
+    let score = match winner {
+        Some(winner) if winner == a => 1.,
+        Some(_) => 0.,
+        None => 0.5,
+    };
+    players[a].results.push((b, score));
+    players[b].results.push((a, 1. - score));
+    winner
+}
+
+/// Like `play`, but a tie goes to the higher seed.
+fn play_elimination(
+    state: &mut State,
+    players: &mut [Player],
+    a: usize,
+    b: usize,
+    config: &MatchConfig,
+) -> (usize, usize) {
+    let (winner, loser) = match play(state, players, a, b, config) {
+        Some(winner) if winner == a => (a, b),
+        Some(_) => (b, a),
+        None => {
+            let (winner, loser) = if players[a].seed < players[b].seed { (a, b) } else { (b, a) };
+            println!("Tied, {} advances on seed.", players[winner].name);
+            (winner, loser)
+        },
+    };
+    players[loser].losses += 1;
+    (winner, loser)
+}
+
+fn swiss(state: &mut State, players: &mut [Player], stages: usize, config: &MatchConfig) {
+    for stage in 1..=stages {
+        let mut order = standings(players);
+        println!("Round {}:", stage);
+        if order.len() % 2 == 1 {
+            let bye = order.iter()
+                .rposition(|&i| players[i].byes == 0)
+                .unwrap_or(order.len() - 1);
+            let bye = order.remove(bye);
+            println!("{} has a bye.", players[bye].label());
+            players[bye].byes += 1;
+        }
+        let pairs = pair_swiss(&order, players).unwrap_or_else(|| {
+            println!("Some bots meet again this round.");
+            order.chunks(2).map(|pair| (pair[0], pair[1])).collect()
+        });
+        for (a, b) in pairs {
+            play(state, players, a, b, config);
+        }
+    }
+    println!("Standings:");
+    println!("  {:<4} {:<20} {:>6} {:>9} {:>9}", "#", "bot", "points", "buchholz", "sonneborn");
+    for (place, i) in standings(players).into_iter().enumerate() {
+        println!("  {:<4} {:<20} {:>6.1} {:>9.1} {:>9.2}",
+                 place + 1, players[i].name, players[i].points(),
+                 buchholz(players, i), sonneborn_berger(players, i));
+    }
+}
+
+/// The players ordered by points, then by the Buchholz and Sonneborn-Berger
+/// tie-breaks, and finally by seed.
+fn standings(players: &[Player]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..players.len()).collect();
+    let key = |i: usize| (players[i].points(), buchholz(players, i), sonneborn_berger(players, i));
+    order.sort_by(|&a, &b| {
+        key(b).partial_cmp(&key(a))
+            .unwrap_or(Ordering::Equal)
+            .then(players[a].seed.cmp(&players[b].seed))
+    });
+    order
+}
+
+/// The sum of the points of the opponents.
+fn buchholz(players: &[Player], i: usize) -> f64 {
+    players[i].results.iter().map(|&(opponent, _)| players[opponent].points()).sum()
+}
+
+/// The sum of the points of the opponents, weighted by the score against
+/// them.
+fn sonneborn_berger(players: &[Player], i: usize) -> f64 {
+    players[i].results.iter().map(|&(opponent, score)| score * players[opponent].points()).sum()
+}
+
+/// How many pairings `pair_swiss` tries before it gives up on avoiding
+/// rematches.
+const PAIRING_BUDGET: usize = 10_000;
+
+/// Pairs players in order of the standings, each with the next player it
+/// hasn't met yet. Returns `None` if rematches can't be avoided, or if no
+/// pairing without them was found within `PAIRING_BUDGET` tries.
+fn pair_swiss(order: &[usize], players: &[Player]) -> Option<Vec<(usize, usize)>> {
+    let mut budget = PAIRING_BUDGET;
+    pair_swiss_within(order, players, &mut budget)
+}
+
+fn pair_swiss_within(
+    order: &[usize],
+    players: &[Player],
+    budget: &mut usize,
+) -> Option<Vec<(usize, usize)>> {
+    let first = match order.first() {
+        Some(&first) => first,
+        None => return Some(Vec::new()),
+    };
+    for k in 1..order.len() {
+        let other = order[k];
+        if players[first].has_played(other) {
+            continue;
+        }
+        if *budget == 0 {
+            return None;
+        }
+        *budget -= 1;
+        let rest: Vec<usize> = order[1..].iter()
+            .cloned()
+            .filter(|&i| i != other)
+            .collect();
+        if let Some(mut pairs) = pair_swiss_within(&rest, players, budget) {
+            pairs.insert(0, (first, other));
+            return Some(pairs);
+        }
+    }
+    None
+}
+
+/// The order of the seeds in a bracket with `size` slots, such that the
+/// highest seeds meet as late as possible.
+fn bracket_order(size: usize) -> Vec<usize> {
+    let mut order = vec![0];
+    while order.len() < size {
+        let len = order.len();
+        order = order.into_iter()
+            .flat_map(|seed| vec![seed, 2 * len - 1 - seed])
+            .collect();
+    }
+    order
+}
+
+fn single_elimination(state: &mut State, players: &mut [Player], config: &MatchConfig) {
+    let size = players.len().next_power_of_two();
+    let mut slots: Vec<Option<usize>> = bracket_order(size).into_iter()
+        .map(|seed| if seed < players.len() { Some(seed) } else { None })
+        .collect();
+    let mut bracket = Vec::new();
+    let mut round = 1;
+    while slots.len() > 1 {
+        println!("{}:", round_name(round, slots.len()));
+        let mut lines = Vec::new();
+        let mut next = Vec::new();
+        for pair in slots.chunks(2) {
+            match (pair[0], pair[1]) {
+                (Some(a), Some(b)) => {
+                    let (winner, _) = play_elimination(state, players, a, b, config);
+                    lines.push(format!("{} vs {}: {} wins",
+                                       players[a].label(), players[b].label(),
+                                       players[winner].name));
+                    next.push(Some(winner));
+                },
+                (Some(a), None) | (None, Some(a)) => {
+                    lines.push(format!("{} has a bye", players[a].label()));
+                    next.push(Some(a));
+                },
+                (None, None) => next.push(None),
+            }
+        }
+        bracket.push((round_name(round, slots.len()), lines));
+        slots = next;
+        round += 1;
+    }
+    print_bracket(&bracket);
+    if let Some(winner) = slots[0] {
+        println!("{} wins the tournament.", players[winner].name);
+    }
+}
+
+fn round_name(round: usize, slots: usize) -> String {
+    match slots {
+        2 => String::from("Final"),
+        4 => String::from("Semifinals"),
+        8 => String::from("Quarterfinals"),
+        _ => format!("Round {}", round),
+    }
+}
+
+/// Runs rounds until one player is left with less than two losses. In each
+/// round the players without losses play each other, and so do the players
+/// with one loss. The best seed of an odd group has a bye.
+fn double_elimination(state: &mut State, players: &mut [Player], config: &MatchConfig) {
+    let mut bracket = Vec::new();
+    let mut eliminated = Vec::new();
+    let mut round = 1;
+    loop {
+        let alive: Vec<usize> = (0..players.len()).filter(|&i| players[i].losses < 2).collect();
+        if alive.len() == 1 {
+            eliminated.push(alive[0]);
+            break;
+        }
+        let winners: Vec<usize> = alive.iter().cloned().filter(|&i| players[i].losses == 0).collect();
+        let losers: Vec<usize> = alive.iter().cloned().filter(|&i| players[i].losses == 1).collect();
+        let groups = if winners.len() == 1 && losers.len() == 1 {
+            vec![("Grand final", alive)]
+        } else {
+            vec![("Winners bracket", winners), ("Losers bracket", losers)]
+        };
+        for (name, group) in groups {
+            if group.len() < 2 {
+                continue;
+            }
+            let title = format!("{} round {}", name, round);
+            println!("{}:", title);
+            let mut lines = Vec::new();
+            let (byes, group) = group.split_at(group.len() % 2);
+            for &bye in byes {
+                lines.push(format!("{} has a bye", players[bye].label()));
+            }
+            let half = group.len() / 2;
+            for k in 0..half {
+                let (a, b) = (group[k], group[group.len() - 1 - k]);
+                let (winner, loser) = play_elimination(state, players, a, b, config);
+                lines.push(format!("{} vs {}: {} wins",
+                                   players[a].label(), players[b].label(),
+                                   players[winner].name));
+                if players[loser].losses == 2 {
+                    eliminated.push(loser);
+                }
+            }
+            bracket.push((title, lines));
+        }
+        round += 1;
+    }
+    print_bracket(&bracket);
+    println!("Final placings:");
+    for (place, &i) in eliminated.iter().rev().enumerate() {
+        println!("  {}. {}", place + 1, players[i].label());
+    }
+}
+
+fn print_bracket(bracket: &[(String, Vec<String>)]) {
+    println!("Bracket:");
+    for (title, lines) in bracket {
+        println!("  {}", title);
+        for line in lines {
+            println!("    {}", line);
+        }
+    }
+}
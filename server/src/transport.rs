@@ -0,0 +1,239 @@
+//! The connections that clients can play over. WebSockets aren't supported,
+//! as they need a handshake and framing that std doesn't provide.
+
+use std::collections::VecDeque;
+use std::io::{Result as IoResult, Error as IoError, Read, Write, ErrorKind};
+use std::net::{Shutdown, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, Sender, RecvTimeoutError};
+use std::thread::spawn;
+use std::time::Duration;
+
+/// A byte stream to a client.
+pub trait Transport: Read + Write + Send {
+    /// Sets the deadline of each read and write, or removes it.
+    fn set_timeout(&mut self, timeout: Option<Duration>) -> IoResult<()>;
+    /// Reads and discards everything that has arrived, without blocking.
+    fn drain(&mut self) -> IoResult<()>;
+    /// Closes the connection.
+    fn shutdown(&mut self) -> IoResult<()>;
+}
+
+impl Transport for TcpStream {
+    fn set_timeout(&mut self, timeout: Option<Duration>) -> IoResult<()> {
+        self.set_read_timeout(timeout)?;
+        self.set_write_timeout(timeout)?;
+        Ok(())
+    }
+    fn drain(&mut self) -> IoResult<()> {
+        self.set_nonblocking(true)?;
+        drain(self);
+        self.set_nonblocking(false)?;
+        Ok(())
+    }
+    fn shutdown(&mut self) -> IoResult<()> {
+        TcpStream::shutdown(self, Shutdown::Both)
+    }
+}
+
+#[cfg(unix)]
+impl Transport for UnixStream {
+    fn set_timeout(&mut self, timeout: Option<Duration>) -> IoResult<()> {
+        self.set_read_timeout(timeout)?;
+        self.set_write_timeout(timeout)?;
+        Ok(())
+    }
+    fn drain(&mut self) -> IoResult<()> {
+        self.set_nonblocking(true)?;
+        drain(self);
+        self.set_nonblocking(false)?;
+        Ok(())
+    }
+    fn shutdown(&mut self) -> IoResult<()> {
+        UnixStream::shutdown(self, Shutdown::Both)
+    }
+}
+
+/// Reads from a nonblocking stream until it would block or is closed.
+fn drain<R: Read>(stream: &mut R) {
+    let mut buf = [0; 1024];
+    loop {
+        match stream.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => continue,
+        }
+    }
+}
+
+/// Bytes that arrive over a channel, which gives pipes and in-memory streams
+/// a read timeout. The channel is disconnected once the other end closes.
+struct ChannelReader {
+    recv: Receiver<Vec<u8>>,
+    buf: VecDeque<u8>,
+    timeout: Option<Duration>,
+}
+impl ChannelReader {
+    fn new(recv: Receiver<Vec<u8>>) -> ChannelReader {
+        ChannelReader {
+            recv,
+            buf: VecDeque::new(),
+            timeout: None,
+        }
+    }
+    fn drain(&mut self) {
+        self.buf.clear();
+        while self.recv.try_recv().is_ok() {}
+    }
+}
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        if self.buf.is_empty() {
+            let bytes = match self.timeout {
+                Some(timeout) => match self.recv.recv_timeout(timeout) {
+                    Ok(bytes) => bytes,
+                    Err(RecvTimeoutError::Timeout) => {
+                        return Err(IoError::new(ErrorKind::TimedOut, "read timed out"));
+                    },
+                    Err(RecvTimeoutError::Disconnected) => return Ok(0),
+                },
+                None => match self.recv.recv() {
+                    Ok(bytes) => bytes,
+                    Err(_) => return Ok(0),
+                },
+            };
+            self.buf.extend(bytes);
+        }
+        let len = buf.len().min(self.buf.len());
+        for (byte, stored) in buf.iter_mut().zip(self.buf.drain(..len)) {
+            *byte = stored;
+        }
+        Ok(len)
+    }
+}
+
+/// A bot running as a child process, which plays over its stdin and stdout.
+/// Writes to the pipe have no deadline.
+pub struct Subprocess {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: ChannelReader,
+}
+impl Subprocess {
+    /// Starts the command with piped stdin and stdout.
+    pub fn spawn(command: &mut Command) -> IoResult<Subprocess> {
+        let mut child = command.stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let mut stdout = child.stdout.take().unwrap();
+        let (send, recv) = channel();
+        spawn(move || {
+            let mut buf = [0; 1024];
+            loop {
+                match stdout.read(&mut buf) {
+                    Ok(0) | Err(_) => return,
+                    Ok(len) => if send.send(buf[..len].to_vec()).is_err() {
+                        return;
+                    },
+                }
+            }
+        });
+        Ok(Subprocess {
+            child,
+            stdin,
+            stdout: ChannelReader::new(recv),
+        })
+    }
+}
+impl Read for Subprocess {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        self.stdout.read(buf)
+    }
+}
+impl Write for Subprocess {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        self.stdin.write(buf)
+    }
+    fn flush(&mut self) -> IoResult<()> {
+        self.stdin.flush()
+    }
+}
+impl Transport for Subprocess {
+    fn set_timeout(&mut self, timeout: Option<Duration>) -> IoResult<()> {
+        self.stdout.timeout = timeout;
+        Ok(())
+    }
+    fn drain(&mut self) -> IoResult<()> {
+        self.stdout.drain();
+        Ok(())
+    }
+    fn shutdown(&mut self) -> IoResult<()> {
+        self.child.kill()?;
+        self.child.wait()?;
+        Ok(())
+    }
+}
+impl Drop for Subprocess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// One end of an in-memory stream.
+pub struct Memory {
+    send: Option<Sender<Vec<u8>>>,
+    recv: ChannelReader,
+}
+impl Memory {
+    /// Creates the two ends of a stream. What is written to one end is read
+    /// from the other.
+    pub fn pair() -> (Memory, Memory) {
+        let (send1, recv1) = channel();
+        let (send2, recv2) = channel();
+        let end1 = Memory {
+            send: Some(send1),
+            recv: ChannelReader::new(recv2),
+        };
+        let end2 = Memory {
+            send: Some(send2),
+            recv: ChannelReader::new(recv1),
+        };
+        (end1, end2)
+    }
+}
+impl Read for Memory {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        self.recv.read(buf)
+    }
+}
+impl Write for Memory {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        let sent = match self.send {
+            Some(ref send) => send.send(buf.to_vec()).is_ok(),
+            None => false,
+        };
+        if sent {
+            Ok(buf.len())
+        } else {
+            Err(IoError::new(ErrorKind::BrokenPipe, "the other end is closed"))
+        }
+    }
+    fn flush(&mut self) -> IoResult<()> {
+        Ok(())
+    }
+}
+impl Transport for Memory {
+    fn set_timeout(&mut self, timeout: Option<Duration>) -> IoResult<()> {
+        self.recv.timeout = timeout;
+        Ok(())
+    }
+    fn drain(&mut self) -> IoResult<()> {
+        self.recv.drain();
+        Ok(())
+    }
+    fn shutdown(&mut self) -> IoResult<()> {
+        self.send = None;
+        Ok(())
+    }
+}
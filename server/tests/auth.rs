@@ -0,0 +1,136 @@
+extern crate rust_client;
+extern crate server;
+
+mod common;
+
+use std::env::temp_dir;
+use std::fs::{remove_file, write};
+use std::net::SocketAddr;
+use std::process;
+use std::thread::{spawn, JoinHandle};
+
+use common::{accept, server, spawn_bot, with_server};
+use rust_client::{zoo, run, Connection, Error};
+use server::State;
+use server::battle::{MatchConfig, Policy};
+
+/// Creates a server that knows the key of team red.
+fn keyed_server(test: &str) -> (State, SocketAddr) {
+    let (mut state, addr) = server();
+    let path = temp_dir().join(format!("rps_keys_{}_{}", process::id(), test));
+    write(&path, "# team key\nred hunter2\n").unwrap();
+    assert_eq!(state.load_keys(&path).unwrap(), 1);
+    remove_file(&path).unwrap();
+    (state, addr)
+}
+
+/// Connects a bot from the zoo that authenticates with the key.
+fn spawn_team_bot(
+    addr: SocketAddr,
+    name: &str,
+    bot: &str,
+    key: &'static str,
+) -> JoinHandle<Result<(), Error>> {
+    let name = String::from(name);
+    let mut bot = zoo::by_name(bot, 0).unwrap();
+    spawn(move || {
+        let mut connection = Connection::connect_authenticated(
+            addr, &name, None, &[], "red", key.as_bytes())?;
+        run(&mut connection, &mut bot)
+    })
+}
+
+fn assert_rejected(res: Result<Connection, Error>, expected: &str) {
+    match res {
+        Err(Error::Rejected(reason)) => assert_eq!(reason, expected),
+        other => panic!("expected a rejection, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn right_key_is_rated() {
+    let (mut state, addr) = keyed_server("right");
+    let rock = spawn_team_bot(addr, "rock", "rock", "hunter2");
+    accept(&mut state, 1);
+    let paper = spawn_team_bot(addr, "paper", "paper", "hunter2");
+    accept(&mut state, 2);
+    assert_eq!(state.clients[0].team.as_deref(), Some("red"));
+    assert!(state.clients.iter().all(|client| client.rated));
+    let (id1, id2) = (state.clients[0].id, state.clients[1].id);
+    state.play_ids(id1, id2, &MatchConfig::new(10, Policy::ForfeitRound)).unwrap();
+    assert_eq!(state.ratings.get(id1).matches, 1);
+    state.shutdown();
+    rock.join().unwrap().unwrap();
+    paper.join().unwrap().unwrap();
+}
+
+#[test]
+fn wrong_key_is_rejected() {
+    let (mut state, addr) = keyed_server("wrong");
+    let res = with_server(&mut state, move || {
+        Connection::connect_authenticated(addr, "impostor", None, &[], "red", b"guess")
+    });
+    assert_rejected(res, "bad-auth");
+    assert!(state.clients.is_empty());
+    state.shutdown();
+}
+
+#[test]
+fn unauthenticated_bot_plays_practice() {
+    let (mut state, addr) = keyed_server("practice");
+    let rock = spawn_team_bot(addr, "rock", "rock", "hunter2");
+    accept(&mut state, 1);
+    let paper = spawn_bot(addr, "paper", "paper");
+    accept(&mut state, 2);
+    assert!(state.clients[0].rated);
+    assert!(!state.clients[1].rated);
+    let (id1, id2) = (state.clients[0].id, state.clients[1].id);
+    let report = state.play_ids(id1, id2, &MatchConfig::new(10, Policy::ForfeitRound)).unwrap();
+    assert_eq!(report.winner(), Some(1));
+    assert_eq!(state.ratings.get(id1).matches, 0);
+    assert_eq!(state.ratings.get(id2).matches, 0);
+    state.shutdown();
+    rock.join().unwrap().unwrap();
+    paper.join().unwrap().unwrap();
+}
+
+#[test]
+fn without_keys_every_bot_is_rated() {
+    let (mut state, addr) = server();
+    let bot = spawn_bot(addr, "anyone", "rock");
+    accept(&mut state, 1);
+    let team_bot = spawn_team_bot(addr, "claimer", "paper", "anything");
+    accept(&mut state, 2);
+    assert!(state.clients.iter().all(|client| client.rated));
+    assert!(state.clients.iter().all(|client| client.team.is_none()));
+    state.shutdown();
+    bot.join().unwrap().unwrap();
+    team_bot.join().unwrap().unwrap();
+}
+
+#[test]
+fn token_resumes_only_same_name_and_team() {
+    let (mut state, addr) = keyed_server("token");
+    let victim = with_server(&mut state, move || {
+        Connection::connect_authenticated(addr, "victim", None, &[], "red", b"hunter2")
+    }).unwrap();
+    let id = state.clients[0].id;
+    let token = String::from(victim.token().unwrap());
+    drop(victim);
+    let stolen = token.clone();
+    let res = with_server(&mut state, move || {
+        Connection::connect_session(addr, "victim", Some(&stolen))
+    });
+    assert_rejected(res, "bad-token");
+    let stolen = token.clone();
+    let res = with_server(&mut state, move || {
+        Connection::connect_authenticated(addr, "impostor", Some(&stolen), &[], "red", b"hunter2")
+    });
+    assert_rejected(res, "bad-token");
+    let _victim = with_server(&mut state, move || {
+        Connection::connect_authenticated(addr, "victim", Some(&token), &[], "red", b"hunter2")
+    }).unwrap();
+    assert_eq!(state.clients.len(), 1);
+    assert_eq!(state.clients[0].id, id);
+    state.shutdown();
+}
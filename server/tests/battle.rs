@@ -0,0 +1,227 @@
+extern crate rust_client;
+extern crate server;
+
+mod common;
+
+use common::{accept, raw_client, server, spawn_bot, spawn_script, spawn_slow};
+use rust_client::{Event, Move};
+use server::battle::{MatchConfig, Policy};
+use server::gauntlet::gauntlet;
+use server::rules::Ruleset;
+
+#[test]
+fn ping_keeps_live_clients() {
+    let (mut state, addr) = server();
+    let bot = spawn_bot(addr, "alive", "rock");
+    accept(&mut state, 1);
+    state.prune_dead();
+    assert_eq!(state.clients.len(), 1);
+    state.shutdown();
+    bot.join().unwrap().unwrap();
+}
+
+#[test]
+fn silent_client_is_pruned() {
+    let (mut state, addr) = server();
+    let _silent = raw_client(addr, b"\x06silent\n");
+    accept(&mut state, 1);
+    state.set_timeout(Some(1));
+    state.prune_dead();
+    assert!(state.clients.is_empty());
+    state.shutdown();
+}
+
+#[test]
+fn full_battle() {
+    let (mut state, addr) = server();
+    let rock = spawn_bot(addr, "rock", "rock");
+    accept(&mut state, 1);
+    let paper = spawn_bot(addr, "paper", "paper");
+    accept(&mut state, 2);
+    let (id1, id2) = (state.clients[0].id, state.clients[1].id);
+    let config = MatchConfig::new(100, Policy::ForfeitRound);
+    let report = state.play_ids(id1, id2, &config).unwrap();
+    assert_eq!(report.rounds, 100);
+    assert_eq!(report.wins, [0, 100]);
+    assert_eq!(report.winner(), Some(1));
+    assert!(state.ratings.elo(id2) > state.ratings.elo(id1));
+    state.shutdown();
+    rock.join().unwrap().unwrap();
+    paper.join().unwrap().unwrap();
+}
+
+#[test]
+fn seed_reproduces_noise() {
+    let (mut state, addr) = server();
+    let rock = spawn_bot(addr, "rock", "rock");
+    accept(&mut state, 1);
+    let paper = spawn_bot(addr, "paper", "paper");
+    accept(&mut state, 2);
+    let (id1, id2) = (state.clients[0].id, state.clients[1].id);
+    let mut config = MatchConfig::new(200, Policy::ForfeitRound);
+    config.noise = 0.5;
+    config.seed = Some(42);
+    let first = state.play_ids(id1, id2, &config).unwrap();
+    let second = state.play_ids(id1, id2, &config).unwrap();
+    assert_eq!(first.wins, second.wins);
+    assert_eq!(first.flips, second.flips);
+    assert!(first.flips[0] > 0);
+    state.shutdown();
+    rock.join().unwrap().unwrap();
+    paper.join().unwrap().unwrap();
+}
+
+#[test]
+fn timeout_forfeits_rounds() {
+    let (mut state, addr) = server();
+    let _silent = raw_client(addr, b"\x06silent\n");
+    accept(&mut state, 1);
+    let bot = spawn_bot(addr, "paper", "paper");
+    accept(&mut state, 2);
+    state.set_timeout(Some(1));
+    let (id1, id2) = (state.clients[0].id, state.clients[1].id);
+    let config = MatchConfig::new(2, Policy::ForfeitRound);
+    let report = state.play_ids(id1, id2, &config).unwrap();
+    assert_eq!(report.violations, [2, 0]);
+    assert_eq!(report.wins, [0, 2]);
+    state.shutdown();
+    bot.join().unwrap().unwrap();
+}
+
+#[test]
+fn noise_spares_forfeited_moves() {
+    let (mut state, addr) = server();
+    let _silent = raw_client(addr, b"\x06silent\n");
+    accept(&mut state, 1);
+    let bot = spawn_bot(addr, "paper", "paper");
+    accept(&mut state, 2);
+    state.set_timeout(Some(1));
+    let (id1, id2) = (state.clients[0].id, state.clients[1].id);
+    let mut config = MatchConfig::new(2, Policy::ForfeitRound);
+    config.noise = 1.;
+    let report = state.play_ids(id1, id2, &config).unwrap();
+    assert_eq!(report.violations, [2, 0]);
+    assert_eq!(report.flips[0], 0);
+    state.shutdown();
+    bot.join().unwrap().unwrap();
+}
+
+#[test]
+fn forfeit_loses_despite_points() {
+    let (mut state, addr) = server();
+    let leader = spawn_slow(addr, "leader", Move::Paper, 2);
+    accept(&mut state, 1);
+    let rock = spawn_bot(addr, "rock", "rock");
+    accept(&mut state, 2);
+    let (id1, id2) = (state.clients[0].id, state.clients[1].id);
+    state.set_timeout(Some(1));
+    let config = MatchConfig::new(5, Policy::ForfeitMatch);
+    let report = state.play_ids(id1, id2, &config).unwrap();
+    assert_eq!(report.wins, [2, 0]);
+    assert_eq!(report.forfeited, [true, false]);
+    assert_eq!(report.winner(), Some(1));
+    state.shutdown();
+    leader.join().unwrap().unwrap();
+    rock.join().unwrap().unwrap();
+}
+
+#[test]
+fn disconnect_aborts_game() {
+    let (mut state, addr) = server();
+    let stayer = spawn_script(addr, "stayer", None);
+    accept(&mut state, 1);
+    let quitter = spawn_script(addr, "quitter", Some(3));
+    accept(&mut state, 2);
+    let (id1, id2) = (state.clients[0].id, state.clients[1].id);
+    let config = MatchConfig::new(100, Policy::ForfeitRound);
+    // The stayer moves first, so its last move has been read when the server
+    // notices that the quitter is gone.
+    assert!(state.play_ids(id1, id2, &config).is_none());
+    quitter.join().unwrap().unwrap();
+    assert_eq!(state.clients.len(), 1);
+    assert_eq!(state.clients[0].id, id1);
+    state.prune_dead();
+    assert_eq!(state.clients.len(), 1);
+    state.shutdown();
+    let events = stayer.join().unwrap().unwrap();
+    assert!(events.contains(&Event::Aborted));
+    assert_eq!(events.last(), Some(&Event::Shutdown));
+}
+
+#[test]
+fn abort_waits_for_late_moves() {
+    let (mut state, addr) = server();
+    let slow = spawn_slow(addr, "slow", Move::Rock, 0);
+    accept(&mut state, 1);
+    let paper = spawn_bot(addr, "paper", "paper");
+    accept(&mut state, 2);
+    let (id1, id2) = (state.clients[0].id, state.clients[1].id);
+    state.set_timeout(Some(1));
+    let config = MatchConfig::new(3, Policy::ForfeitMatch);
+    let report = state.play_ids(id1, id2, &config).unwrap();
+    assert_eq!(report.forfeited, [true, false]);
+    // The move that timed out was read before the abort was acknowledged,
+    // so it isn't mistaken for a ping reply or a move of the next game.
+    state.prune_dead();
+    assert_eq!(state.clients.len(), 2);
+    state.set_timeout(Some(5));
+    let config = MatchConfig::new(2, Policy::ForfeitRound);
+    let report = state.play_ids(id1, id2, &config).unwrap();
+    assert_eq!(report.violations, [0, 0]);
+    assert_eq!(report.wins, [0, 2]);
+    state.shutdown();
+    slow.join().unwrap().unwrap();
+    paper.join().unwrap().unwrap();
+}
+
+#[test]
+fn abort_gives_up_on_flooding_client() {
+    let (mut state, addr) = server();
+    let mut handshake = b"\x05flood?\x05abort".to_vec();
+    handshake.extend_from_slice(&[b'q'; 200]);
+    let _flood = raw_client(addr, &handshake);
+    accept(&mut state, 1);
+    let script = spawn_script(addr, "script", None);
+    accept(&mut state, 2);
+    let (id1, id2) = (state.clients[0].id, state.clients[1].id);
+    state.set_timeout(None);
+    let config = MatchConfig::new(3, Policy::ForfeitMatch);
+    // The junk never contains the sync token, so the abort gives up instead
+    // of reading forever. The forfeit stands and the client is removed.
+    let report = state.play_ids(id1, id2, &config).unwrap();
+    assert_eq!(report.winner(), Some(1));
+    assert_eq!(state.clients.len(), 1);
+    assert_eq!(state.clients[0].id, id2);
+    state.shutdown();
+    // The opponent's game is aborted once.
+    let events = script.join().unwrap().unwrap();
+    assert_eq!(events.iter().filter(|&event| *event == Event::Aborted).count(), 1);
+}
+
+#[test]
+fn ruleset_fits_in_info_line() {
+    let payoff = vec!["0"; 81].join(",");
+    assert!(Ruleset::new("nine", "abcdfghij", &payoff).is_err());
+    let payoff = vec!["-1000000000"; 64].join(",");
+    assert!(Ruleset::new("wide", "abcdfghi", &payoff).is_err());
+    let payoff = vec!["0"; 64].join(",");
+    let rules = Ruleset::new("eight", "abcdfghi", &payoff).unwrap();
+    assert!(rules.describe().len() + " seed=18446744073709551615".len() <= 255);
+}
+
+#[test]
+fn gauntlet_is_unrated() {
+    let (mut state, addr) = server();
+    let rock = spawn_bot(addr, "rock", "rock");
+    accept(&mut state, 1);
+    let paper = spawn_bot(addr, "paper", "paper");
+    accept(&mut state, 2);
+    state.clients[0].reference = true;
+    let (id1, id2) = (state.clients[0].id, state.clients[1].id);
+    gauntlet(&mut state, "paper", &MatchConfig::new(10, Policy::ForfeitRound));
+    assert_eq!(state.ratings.get(id1).matches, 0);
+    assert_eq!(state.ratings.get(id2).matches, 0);
+    state.shutdown();
+    rock.join().unwrap().unwrap();
+    paper.join().unwrap().unwrap();
+}
@@ -0,0 +1,135 @@
+//! Starts a server on an ephemeral port and connects mock clients to it.
+
+#![allow(dead_code)]
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread::{sleep, spawn, JoinHandle};
+use std::time::{Duration, Instant};
+
+use rust_client::{zoo, run, Connection, Error, Event, Move};
+use server::State;
+
+/// Creates a state that accepts clients on a free port of localhost.
+pub fn server() -> (State, SocketAddr) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut state = State::new();
+    state.listen_on(listener);
+    (state, addr)
+}
+
+/// Runs `f` on another thread while the state accepts clients, and returns
+/// its result. Extended handshakes block until the server answers, so they
+/// have to be made this way.
+pub fn with_server<T, F>(state: &mut State, f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let handle = spawn(f);
+    let start = Instant::now();
+    while !handle.is_finished() {
+        assert!(start.elapsed() < Duration::new(10, 0), "the client never finished");
+        state.print_messages();
+        sleep(Duration::from_millis(10));
+    }
+    handle.join().unwrap()
+}
+
+/// Accepts clients until there are `count` of them.
+pub fn accept(state: &mut State, count: usize) {
+    state.wait_for_clients(count, Some(5));
+    assert_eq!(state.clients.len(), count);
+}
+
+/// Connects a reference bot from the zoo, which plays until the server shuts
+/// down.
+pub fn spawn_bot(addr: SocketAddr, name: &str, bot: &str) -> JoinHandle<Result<(), Error>> {
+    let name = String::from(name);
+    let mut bot = zoo::by_name(bot, 0).unwrap();
+    spawn(move || {
+        let mut connection = Connection::connect_session(addr, &name, None)?;
+        run(&mut connection, &mut bot)
+    })
+}
+
+/// Connects a client that plays rock and records every event until the
+/// server shuts down, or until it has played `quit_after` moves, at which
+/// point it disconnects.
+pub fn spawn_script(
+    addr: SocketAddr,
+    name: &str,
+    quit_after: Option<usize>,
+) -> JoinHandle<Result<Vec<Event>, Error>> {
+    let name = String::from(name);
+    spawn(move || {
+        let mut connection = Connection::connect_session(addr, &name, None)?;
+        let mut events = Vec::new();
+        let mut moves = 0;
+        loop {
+            let event = connection.next_event()?;
+            let answer = match event {
+                Event::NewGame(_) | Event::OpponentMoved(_) => true,
+                Event::Shutdown => {
+                    events.push(event);
+                    return Ok(events);
+                },
+                _ => false,
+            };
+            events.push(event);
+            if answer {
+                if quit_after == Some(moves) {
+                    return Ok(events);
+                }
+                connection.send_move(Move::Rock)?;
+                moves += 1;
+            }
+        }
+    })
+}
+
+/// Connects a client that asks for synced aborts and plays `m`. After its
+/// first `fast` moves it takes one and a half seconds for each move.
+pub fn spawn_slow(
+    addr: SocketAddr,
+    name: &str,
+    m: Move,
+    fast: usize,
+) -> JoinHandle<Result<(), Error>> {
+    let name = String::from(name);
+    spawn(move || {
+        let mut connection = Connection::connect_session_with(addr, &name, None, &["abort"])?;
+        let mut moves = 0;
+        loop {
+            match connection.next_event()? {
+                Event::NewGame(_) | Event::OpponentMoved(_) => {
+                    if moves >= fast {
+                        sleep(Duration::from_millis(1500));
+                    }
+                    connection.send_move(m)?;
+                    moves += 1;
+                },
+                Event::Shutdown => return Ok(()),
+                _ => {},
+            }
+        }
+    })
+}
+
+/// Connects with a raw socket and sends `handshake`.
+pub fn raw_client(addr: SocketAddr, handshake: &[u8]) -> TcpStream {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(handshake).unwrap();
+    stream.set_read_timeout(Some(Duration::new(10, 0))).unwrap();
+    stream
+}
+
+/// Reads a length prefixed line from a raw socket.
+pub fn read_line(stream: &mut TcpStream) -> String {
+    let mut len = [0];
+    stream.read_exact(&mut len).unwrap();
+    let mut line = vec![0; usize::from(len[0])];
+    stream.read_exact(&mut line).unwrap();
+    String::from_utf8(line).unwrap()
+}
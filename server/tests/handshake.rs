@@ -0,0 +1,223 @@
+extern crate rust_client;
+extern crate server;
+
+mod common;
+
+use std::io::Read;
+use std::thread::spawn;
+use std::time::{Duration, Instant};
+
+use common::{accept, raw_client, read_line, server, with_server};
+use rust_client::{Connection, Error};
+
+#[test]
+fn legacy_handshake() {
+    let (mut state, addr) = server();
+    let client = spawn(move || Connection::connect(addr, "legacy").unwrap());
+    accept(&mut state, 1);
+    assert_eq!(state.clients[0].name, "legacy");
+    assert!(state.clients[0].options.is_none());
+    let _connection = client.join().unwrap();
+    state.shutdown();
+}
+
+#[test]
+fn extended_handshake_gives_token() {
+    let (mut state, addr) = server();
+    let connection = with_server(&mut state, move || {
+        Connection::connect_session_with(addr, "extended", None, &["rules", "seed"])
+    }).unwrap();
+    assert_eq!(state.clients.len(), 1);
+    assert_eq!(state.clients[0].token.as_deref(), connection.token());
+    assert!(connection.token().is_some());
+    assert_eq!(state.clients[0].option("seed"), Some(""));
+    state.shutdown();
+}
+
+#[test]
+fn token_resumes_session() {
+    let (mut state, addr) = server();
+    let first = with_server(&mut state, move || {
+        Connection::connect_session(addr, "resumed", None)
+    }).unwrap();
+    let id = state.clients[0].id;
+    let token = String::from(first.token().unwrap());
+    drop(first);
+    let second = with_server(&mut state, move || {
+        Connection::connect_session(addr, "resumed", Some(&token))
+    }).unwrap();
+    assert_eq!(state.clients.len(), 1);
+    assert_eq!(state.clients[0].id, id);
+    drop(second);
+    state.shutdown();
+}
+
+#[test]
+fn expired_session_is_not_resumed() {
+    let (mut state, addr) = server();
+    let first = with_server(&mut state, move || {
+        Connection::connect_session(addr, "forgotten", None)
+    }).unwrap();
+    let id = state.clients[0].id;
+    let token = String::from(first.token().unwrap());
+    // A connected client keeps its session however long it stays.
+    state.expire_sessions(Duration::new(0, 0));
+    drop(first);
+    state.prune_dead();
+    let resumed = token.clone();
+    let second = with_server(&mut state, move || {
+        Connection::connect_session(addr, "forgotten", Some(&resumed))
+    }).unwrap();
+    assert_eq!(state.clients[0].id, id);
+    drop(second);
+    state.prune_dead();
+    assert!(state.clients.is_empty());
+    state.expire_sessions(Duration::new(0, 0));
+    let _third = with_server(&mut state, move || {
+        Connection::connect_session(addr, "forgotten", Some(&token))
+    }).unwrap();
+    assert_ne!(state.clients[0].id, id);
+    state.shutdown();
+}
+
+#[test]
+fn duplicate_name_is_rejected() {
+    let (mut state, addr) = server();
+    let _first = with_server(&mut state, move || {
+        Connection::connect_session(addr, "twin", None)
+    }).unwrap();
+    let second = with_server(&mut state, move || {
+        Connection::connect_session(addr, "twin", None)
+    });
+    match second {
+        Err(Error::Rejected(reason)) => assert_eq!(reason, "duplicate-name"),
+        _ => panic!("the second twin was accepted"),
+    }
+    assert_eq!(state.clients.len(), 1);
+    state.shutdown();
+}
+
+#[test]
+fn invalid_name_is_rejected() {
+    let (mut state, addr) = server();
+    let reply = with_server(&mut state, move || {
+        let mut stream = raw_client(addr, b"\x01\x07?\x00");
+        read_line(&mut stream)
+    });
+    assert_eq!(reply, "reject invalid-name");
+    assert!(state.clients.is_empty());
+    state.shutdown();
+}
+
+#[test]
+fn truncated_handshake_is_dropped() {
+    let (mut state, addr) = server();
+    drop(raw_client(addr, b"\x05ab"));
+    let mut stream = raw_client(addr, b"\x04good\n");
+    accept(&mut state, 1);
+    assert_eq!(state.clients[0].name, "good");
+    state.shutdown();
+    let mut buf = [0];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"x");
+}
+
+#[test]
+fn silent_connection_does_not_hold_up_others() {
+    let (mut state, addr) = server();
+    let _silent = raw_client(addr, b"");
+    let start = Instant::now();
+    let _stream = raw_client(addr, b"\x04good\n");
+    accept(&mut state, 1);
+    // The silent connection would only time out after 10 s.
+    assert!(start.elapsed() < Duration::new(5, 0));
+    state.shutdown();
+}
+
+#[test]
+fn banned_name_is_rejected() {
+    let (mut state, addr) = server();
+    state.ban("villain");
+    let result = with_server(&mut state, move || {
+        Connection::connect_session(addr, "villain", None)
+    });
+    match result {
+        Err(Error::Rejected(reason)) => assert_eq!(reason, "banned"),
+        _ => panic!("the banned bot was accepted"),
+    }
+    assert!(state.clients.is_empty());
+    state.shutdown();
+}
+
+#[test]
+fn banned_address_is_refused() {
+    let (mut state, addr) = server();
+    state.ban("127.0.0.1");
+    let result = with_server(&mut state, move || {
+        Connection::connect_session(addr, "innocent", None)
+    });
+    assert!(result.is_err());
+    state.unban("127.0.0.1");
+    let _connection = with_server(&mut state, move || {
+        Connection::connect_session(addr, "innocent", None)
+    }).unwrap();
+    assert_eq!(state.clients.len(), 1);
+    state.shutdown();
+}
+
+#[test]
+fn renamed_session_keeps_name() {
+    let (mut state, addr) = server();
+    let first = with_server(&mut state, move || {
+        Connection::connect_session(addr, "before", None)
+    }).unwrap();
+    state.rename("before", "after");
+    assert_eq!(state.clients[0].name, "after");
+    let token = String::from(first.token().unwrap());
+    drop(first);
+    let _second = with_server(&mut state, move || {
+        Connection::connect_session(addr, "before", Some(&token))
+    }).unwrap();
+    assert_eq!(state.clients.len(), 1);
+    assert_eq!(state.clients[0].name, "after");
+    state.shutdown();
+}
+
+#[test]
+fn client_limits_refuse_connections() {
+    let (mut state, addr) = server();
+    state.limits.lock().unwrap().max_per_ip = Some(1);
+    let _first = with_server(&mut state, move || {
+        Connection::connect_session(addr, "first", None)
+    }).unwrap();
+    let second = with_server(&mut state, move || {
+        Connection::connect_session(addr, "second", None)
+    });
+    assert!(second.is_err());
+    {
+        let mut limits = state.limits.lock().unwrap();
+        limits.max_per_ip = None;
+        limits.max_clients = Some(1);
+    }
+    let third = with_server(&mut state, move || {
+        Connection::connect_session(addr, "third", None)
+    });
+    assert!(third.is_err());
+    assert_eq!(state.clients.len(), 1);
+    state.shutdown();
+}
+
+#[test]
+fn fast_connections_are_throttled() {
+    let (mut state, addr) = server();
+    state.limits.lock().unwrap().accept_rate = Some(1.);
+    let _first = with_server(&mut state, move || {
+        Connection::connect_session(addr, "first", None)
+    }).unwrap();
+    let second = with_server(&mut state, move || {
+        Connection::connect_session(addr, "second", None)
+    });
+    assert!(second.is_err());
+    assert_eq!(state.clients.len(), 1);
+    state.shutdown();
+}
@@ -0,0 +1,62 @@
+extern crate rust_client;
+extern crate server;
+
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use common::{accept, server, spawn_bot};
+use server::{commands, ladder, State};
+use server::battle::Policy;
+
+/// Waits until `done` holds for the state, polling it without holding the
+/// lock in between.
+fn wait_until<F: Fn(&mut State) -> bool>(state: &Mutex<State>, done: F) {
+    let start = Instant::now();
+    while !done(&mut state.lock().unwrap()) {
+        assert!(start.elapsed() < Duration::new(20, 0), "the ladder never got there");
+        sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn kick_and_rename_apply_after_ladder_match() {
+    let (mut state, addr) = server();
+    let rock = spawn_bot(addr, "rock", "rock");
+    accept(&mut state, 1);
+    let paper = spawn_bot(addr, "paper", "paper");
+    accept(&mut state, 2);
+    commands::execute(&mut state, "ladder resume 2000");
+    let state = Arc::new(Mutex::new(state));
+    let handle = ladder::ladder_thread(state.clone());
+    // Both bots are checked out while they play.
+    wait_until(&state, |state| state.clients.is_empty());
+    {
+        let mut state = state.lock().unwrap();
+        commands::execute(&mut state, "kick rock");
+        commands::execute(&mut state, "rename paper sheet");
+        commands::execute(&mut state, "ladder pause");
+    }
+    wait_until(&state, |state| !state.clients.is_empty());
+    handle.shutdown();
+    let mut state = state.lock().unwrap();
+    assert_eq!(state.clients.len(), 1);
+    assert_eq!(state.clients[0].name, "sheet");
+    state.shutdown();
+    rock.join().unwrap().unwrap();
+    paper.join().unwrap().unwrap();
+}
+
+#[test]
+fn resumed_ladder_follows_policy() {
+    let (mut state, _) = server();
+    commands::execute(&mut state, "policy match");
+    commands::execute(&mut state, "ladder resume");
+    assert!(matches!(state.ladder.config.policy, Policy::ForfeitMatch));
+    commands::execute(&mut state, "policy random");
+    commands::execute(&mut state, "ladder resume 100");
+    assert!(matches!(state.ladder.config.policy, Policy::RandomMove));
+    state.shutdown();
+}
@@ -0,0 +1,69 @@
+extern crate rust_client;
+extern crate server;
+
+mod common;
+
+use common::{accept, server, spawn_bot, spawn_slow};
+use rust_client::Move;
+use server::battle::{MatchConfig, Policy};
+use server::rating::INITIAL_RATING;
+use server::tournament::{tournament, Format};
+
+#[test]
+fn win_moves_ratings() {
+    let (mut state, addr) = server();
+    let rock = spawn_bot(addr, "rock", "rock");
+    accept(&mut state, 1);
+    let paper = spawn_bot(addr, "paper", "paper");
+    accept(&mut state, 2);
+    let (id1, id2) = (state.clients[0].id, state.clients[1].id);
+    state.play_ids(id1, id2, &MatchConfig::new(10, Policy::ForfeitRound)).unwrap();
+    let (elo1, elo2) = (state.ratings.elo(id1), state.ratings.elo(id2));
+    assert!(elo1 < INITIAL_RATING && elo2 > INITIAL_RATING);
+    assert!((elo1 + elo2 - 2. * INITIAL_RATING).abs() < 1e-9);
+    state.shutdown();
+    rock.join().unwrap().unwrap();
+    paper.join().unwrap().unwrap();
+}
+
+#[test]
+fn forfeit_lowers_rating() {
+    let (mut state, addr) = server();
+    let leader = spawn_slow(addr, "leader", Move::Paper, 2);
+    accept(&mut state, 1);
+    let rock = spawn_bot(addr, "rock", "rock");
+    accept(&mut state, 2);
+    let (id1, id2) = (state.clients[0].id, state.clients[1].id);
+    state.set_timeout(Some(1));
+    let report = state.play_ids(id1, id2, &MatchConfig::new(5, Policy::ForfeitMatch)).unwrap();
+    assert!(report.score[0] > report.score[1]);
+    assert!(state.ratings.elo(id1) < INITIAL_RATING);
+    assert!(state.ratings.elo(id2) > INITIAL_RATING);
+    state.shutdown();
+    leader.join().unwrap().unwrap();
+    rock.join().unwrap().unwrap();
+}
+
+#[test]
+fn swiss_avoids_rematches() {
+    let (mut state, addr) = server();
+    let names = ["rock", "paper", "scissor", "cycle"];
+    let bots: Vec<_> = names.iter().enumerate().map(|(i, &name)| {
+        let bot = spawn_bot(addr, name, name);
+        accept(&mut state, i + 1);
+        bot
+    }).collect();
+    tournament(&mut state, Format::Swiss(3), &MatchConfig::new(10, Policy::ForfeitRound));
+    // Three stages between four bots is a round robin.
+    let ids: Vec<usize> = state.clients.iter().map(|client| client.id).collect();
+    for (i, &id1) in ids.iter().enumerate() {
+        assert_eq!(state.ratings.get(id1).matches, 3);
+        for &id2 in &ids[i + 1..] {
+            assert_eq!(state.ladder.played(id1, id2), 1);
+        }
+    }
+    state.shutdown();
+    for bot in bots {
+        bot.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,105 @@
+extern crate rust_client;
+extern crate server;
+
+mod common;
+
+use std::io::Write;
+use std::thread::{spawn, JoinHandle};
+
+use common::accept;
+use rust_client::{run, zoo, Connection, Error};
+use server::State;
+use server::battle::{MatchConfig, Policy};
+use server::client::Client;
+use server::transport::{Memory, Transport};
+
+/// Makes a legacy handshake over the stream and plays the bot on it until
+/// the server shuts down.
+fn spawn_bot<S>(mut stream: S, name: &str, bot: &str) -> JoinHandle<Result<(), Error>>
+where
+    S: Transport + 'static,
+{
+    let mut handshake = vec![name.len() as u8];
+    handshake.extend_from_slice(name.as_bytes());
+    handshake.push(b'\n');
+    stream.write_all(&handshake).unwrap();
+    let mut bot = zoo::by_name(bot, 0).unwrap();
+    spawn(move || {
+        let mut connection = Connection::from_stream(stream, &[]);
+        run(&mut connection, &mut bot)
+    })
+}
+
+/// Connects a bot to the state over an in-memory stream.
+fn memory_bot(state: &mut State, name: &str, bot: &str) -> JoinHandle<Result<(), Error>> {
+    let (server_end, client_end) = Memory::pair();
+    let handle = spawn_bot(client_end, name, bot);
+    let client = Client::new(String::from("memory"), Box::new(server_end)).unwrap();
+    state.add_client(client);
+    handle
+}
+
+#[test]
+fn memory_battle() {
+    let mut state = State::new();
+    let rock = memory_bot(&mut state, "rock", "rock");
+    let paper = memory_bot(&mut state, "paper", "paper");
+    let (id1, id2) = (state.clients[0].id, state.clients[1].id);
+    state.prune_dead();
+    let config = MatchConfig::new(100, Policy::ForfeitRound);
+    let report = state.play_ids(id1, id2, &config).unwrap();
+    assert_eq!(report.wins, [0, 100]);
+    state.shutdown();
+    rock.join().unwrap().unwrap();
+    paper.join().unwrap().unwrap();
+}
+
+#[test]
+fn memory_timeout() {
+    let mut state = State::new();
+    let (server_end, mut client_end) = Memory::pair();
+    client_end.write_all(b"\x06silent\n").unwrap();
+    let client = Client::new(String::from("memory"), Box::new(server_end)).unwrap();
+    state.add_client(client);
+    let paper = memory_bot(&mut state, "paper", "paper");
+    state.set_timeout(Some(1));
+    let (id1, id2) = (state.clients[0].id, state.clients[1].id);
+    let config = MatchConfig::new(2, Policy::ForfeitRound);
+    let report = state.play_ids(id1, id2, &config).unwrap();
+    assert_eq!(report.violations, [2, 0]);
+    state.prune_dead();
+    assert_eq!(state.clients.len(), 1);
+    state.shutdown();
+    paper.join().unwrap().unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn unix_socket_against_memory() {
+    use std::os::unix::net::UnixStream;
+    use std::env::temp_dir;
+    use std::fs::remove_file;
+    use std::process::id;
+
+    let path = temp_dir().join(format!("rps-test-{}.sock", id()));
+    let _ = remove_file(&path);
+    let mut state = State::new();
+    state.listen_unix(path.clone());
+    let stream = loop {
+        match UnixStream::connect(&path) {
+            Ok(stream) => break stream,
+            Err(_) => std::thread::sleep(std::time::Duration::from_millis(10)),
+        }
+    };
+    let scissor = spawn_bot(stream, "scissor", "scissor");
+    accept(&mut state, 1);
+    let paper = memory_bot(&mut state, "paper", "paper");
+    let (id1, id2) = (state.clients[0].id, state.clients[1].id);
+    let config = MatchConfig::new(50, Policy::ForfeitRound);
+    let report = state.play_ids(id1, id2, &config).unwrap();
+    assert_eq!(report.wins, [50, 0]);
+    state.shutdown();
+    scissor.join().unwrap().unwrap();
+    paper.join().unwrap().unwrap();
+    let _ = remove_file(&path);
+}